@@ -0,0 +1,141 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use oviiirs_archive::lzss::{compress_with_level, decompress, CompressionLevel};
+use std::fs;
+use std::hint::black_box;
+
+// Small linear congruential generator so the data is the same on every run.
+struct Lcg(u32);
+
+impl Lcg {
+    fn next(&mut self) -> u32 {
+        self.0 = self.0.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+        self.0 >> 16
+    }
+}
+
+// Dialog the way field msd files hold it: FF8 text codes with names and pauses mixed in.
+fn field_text(size: usize) -> Vec<u8> {
+    let words: [&[u8]; 12] = [
+        b"Squall",
+        b"Rinoa",
+        b"Seifer",
+        b"Garden",
+        b"SeeD",
+        b"Balamb",
+        b"the",
+        b"is",
+        b"to",
+        b"Whatever...",
+        b"Headmaster",
+        b"Cid",
+    ];
+    let mut rng = Lcg(8);
+    let mut data = Vec::with_capacity(size);
+    while data.len() < size {
+        data.push(0x03);
+        data.push(0x30 + (rng.next() % 8) as u8);
+        for _ in 0..4 + rng.next() % 8 {
+            data.extend_from_slice(words[rng.next() as usize % words.len()]);
+            data.push(b' ');
+        }
+        data.extend_from_slice(&[0x02, 0x00]);
+    }
+    data.truncate(size);
+    data
+}
+
+// A 4bpp TIM: header, a 16 colour CLUT and pixels that are mostly smooth with some noise.
+fn tim_texture(size: usize) -> Vec<u8> {
+    let mut rng = Lcg(3);
+    let mut data = vec![0x10, 0, 0, 0, 0x08, 0, 0, 0];
+    data.extend((0..32).map(|i| (i * 7) as u8));
+    let mut pixel = 0u8;
+    while data.len() < size {
+        if rng.next().is_multiple_of(5) {
+            pixel = rng.next() as u8;
+        }
+        data.push(pixel);
+    }
+    data.truncate(size);
+    data
+}
+
+// Vertex data of a field model, i16 triples that change a little at a time.
+fn model_vertices(size: usize) -> Vec<u8> {
+    let mut rng = Lcg(42);
+    let mut vertex = [0i16; 3];
+    let mut data = Vec::with_capacity(size);
+    while data.len() < size {
+        for coordinate in &mut vertex {
+            *coordinate = coordinate.wrapping_add((rng.next() % 33) as i16 - 16);
+            data.extend_from_slice(&coordinate.to_le_bytes());
+        }
+        data.extend_from_slice(&[0, 0]);
+    }
+    data.truncate(size);
+    data
+}
+
+// Files extracted from the game in the directory named by OVIIIRS_BENCH_DATA, or generated data
+// that looks like them when it is not set.
+fn inputs() -> Vec<(String, Vec<u8>)> {
+    let Some(directory) = std::env::var_os("OVIIIRS_BENCH_DATA") else {
+        let size = 256 * 1024;
+        return vec![
+            ("field_text".to_string(), field_text(size)),
+            ("tim_texture".to_string(), tim_texture(size)),
+            ("model_vertices".to_string(), model_vertices(size)),
+        ];
+    };
+    let mut paths: Vec<_> = fs::read_dir(&directory)
+        .expect("OVIIIRS_BENCH_DATA should name a directory")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.is_file())
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty(), "OVIIIRS_BENCH_DATA holds no files");
+    paths
+        .into_iter()
+        .map(|path| {
+            let name = path.file_name().unwrap().to_string_lossy().into_owned();
+            (name, fs::read(&path).unwrap())
+        })
+        .collect()
+}
+
+fn bench_lzss(c: &mut Criterion) {
+    let inputs = inputs();
+
+    let mut group = c.benchmark_group("lzss_compress");
+    group.sample_size(10);
+    for (name, data) in &inputs {
+        group.throughput(Throughput::Bytes(data.len() as u64));
+        for level in [
+            CompressionLevel::Fast,
+            CompressionLevel::Balanced,
+            CompressionLevel::Exhaustive,
+        ] {
+            group.bench_with_input(
+                BenchmarkId::new(format!("{:?}", level), name),
+                data,
+                |b, data| b.iter(|| compress_with_level(black_box(data), level)),
+            );
+        }
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("lzss_decompress");
+    for (name, data) in &inputs {
+        let compressed = compress_with_level(data, CompressionLevel::Balanced);
+        group.throughput(Throughput::Bytes(data.len() as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(name),
+            &compressed,
+            |b, compressed| b.iter(|| decompress(black_box(compressed), data.len())),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_lzss);
+criterion_main!(benches);
@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use oviiirs_archive::parse_fi_entries;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = parse_fi_entries(data);
+});
@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use oviiirs_archive::parse_fl_entries;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = parse_fl_entries(data);
+});
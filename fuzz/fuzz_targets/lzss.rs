@@ -0,0 +1,21 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use oviiirs_archive::oviiirs_archive::FI;
+use oviiirs_archive::{lzss_decompress, read_entry_bytes_from_memory, CompressionTypeT};
+
+// The first four bytes are the uncompressed size the fi would declare, the rest is a fs
+// holding one framed entry at offset 0.
+fuzz_target!(|data: &[u8]| {
+    let Some((size, fs_bytes)) = data.split_first_chunk::<4>() else {
+        return;
+    };
+    let uncompressed_size = u32::from_le_bytes(*size);
+    let fi = FI {
+        uncompressed_size,
+        offset: 0,
+        compression_type: CompressionTypeT::Lzss,
+    };
+    let _ = read_entry_bytes_from_memory(fs_bytes, &fi);
+    let _ = lzss_decompress(fs_bytes, uncompressed_size as usize);
+});
@@ -0,0 +1,15 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use oviiirs_archive::{read_data_from_reader, read_entry_bytes_from_reader};
+use std::io::Cursor;
+
+// The header and then every entry it points at, read back out of the same bytes.
+fuzz_target!(|data: &[u8]| {
+    let mut cursor = Cursor::new(data);
+    if let Ok(header) = read_data_from_reader(&mut cursor) {
+        for entry in &header.entries {
+            let _ = read_entry_bytes_from_reader(entry, &mut cursor);
+        }
+    }
+});
@@ -0,0 +1,626 @@
+use crate::archive_reader::ArchiveReader;
+use crate::error::{ArchiveError, ArchiveResult, ErrorLocation, ResultExt};
+use crate::fiflfs_writer::{frame_entry, FIFLFSStreams};
+use crate::oviiirs_archive::{
+    check_fi_fl_counts, parse_fi_entries, parse_fl_entries, read_entry_bytes_from_file,
+    CompressionTypeT, ConvertFromZZZEntryAndFile, CreateDirectories, FIfile, FLfile,
+    GenerateWindowsPath, WriteEntry, ZZZEntry, ZZZHeader, ZZZfiles, FI, FIFLFSZZZ, FL,
+};
+use crate::vfs::normalize_archive_path;
+use crate::zzz_writer::ZZZWriter;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// A change to one entry of a FIFLFS archive.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EntryEdit {
+    /// Swaps the contents of an entry. A `compression_type` of `None` keeps the entry's current
+    /// compression.
+    Replace {
+        data: Vec<u8>,
+        compression_type: Option<CompressionTypeT>,
+    },
+    /// Appends a new entry to the archive whose fs is `archive`, e.g. `data\lang-en\menu.fs` or
+    /// the fl path of a fs nested in field.
+    Add {
+        archive: String,
+        data: Vec<u8>,
+        compression_type: CompressionTypeT,
+    },
+    Remove,
+}
+
+/// Reads the decompressed fi, fl and fs of an archive. A `.zzz` is opened once for all three.
+pub fn read_fiflfs_streams(archive: &FIFLFSZZZ) -> ArchiveResult<FIFLFSStreams> {
+    let fs_reader = ArchiveReader::open(archive.fs_file_path())?;
+    let read = |entry: &ZZZEntry, file_path: &str| {
+        if file_path == fs_reader.file_path() {
+            read_entry_bytes_from_file(entry, &fs_reader)
+        } else {
+            read_entry_bytes_from_file(entry, &ArchiveReader::open(file_path)?)
+        }
+    };
+    Ok(FIFLFSStreams {
+        fi: read(&archive.fi, archive.fi_file_path())?,
+        fl: read(&archive.fl, archive.fl_file_path())?,
+        fs: read(&archive.fs, archive.fs_file_path())?,
+    })
+}
+
+// The bytes an entry occupies in the fs, including the size header of compressed entries.
+pub(crate) fn raw_entry_bytes<'a>(fs_bytes: &'a [u8], fi: &FI) -> ArchiveResult<&'a [u8]> {
+    if fi.uncompressed_size == 0 {
+        return Ok(&[]);
+    }
+    let start = fi.offset as usize;
+    let length = match fi.compression_type {
+        CompressionTypeT::None => fi.uncompressed_size as usize,
+        CompressionTypeT::Lzss | CompressionTypeT::Lz4 => fs_bytes
+            .get(start..start + 4)
+            .map(|size| 4 + u32::from_le_bytes(size.try_into().unwrap()) as usize)
+            .unwrap_or(usize::MAX),
+    };
+    start
+        .checked_add(length)
+        .and_then(|end| fs_bytes.get(start..end))
+        .ok_or_else(|| {
+            ArchiveError::EntryOutOfBounds {
+                location: ErrorLocation::default(),
+                size: length as u64,
+                available: fs_bytes.len().saturating_sub(start) as u64,
+            }
+            .with_offset(fi.offset as u64)
+        })
+}
+
+/// Applies `edit` to the entry `fl_path` and returns the rewritten streams. Every other entry
+/// keeps its compressed bytes, only the offsets in the fi change.
+pub fn edit_fiflfs_streams(
+    streams: &FIFLFSStreams,
+    fl_path: &str,
+    edit: &EntryEdit,
+) -> ArchiveResult<FIFLFSStreams> {
+    let fi_file = FIfile {
+        entries: parse_fi_entries(&streams.fi)?,
+        ..Default::default()
+    };
+    let fl_file = FLfile {
+        entries: parse_fl_entries(&streams.fl)?,
+        ..Default::default()
+    };
+    check_fi_fl_counts(&fi_file, &fl_file)?;
+    let (fi_entries, fl_entries) = (fi_file.entries, fl_file.entries);
+
+    let target = normalize_archive_path(fl_path);
+    let index = fl_entries
+        .iter()
+        .position(|fl| normalize_archive_path(fl) == target);
+    match (edit, index) {
+        (EntryEdit::Add { .. }, Some(_)) => {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} is already in the archive", fl_path),
+            )
+            .into())
+        }
+        (EntryEdit::Replace { .. } | EntryEdit::Remove, None) => {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} is not in the archive", fl_path),
+            )
+            .into())
+        }
+        _ => {}
+    }
+
+    let mut result = FIFLFSStreams::default();
+    let mut push = |fi: FI, fl: &FL, fs_bytes: &[u8]| -> ArchiveResult<()> {
+        let offset = u32::try_from(result.fs.len()).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} starts past the 4GiB limit of the fs file", fl),
+            )
+        })?;
+        FI { offset, ..fi }.write_entry(&mut result.fi)?;
+        fl.write_entry(&mut result.fl)?;
+        result.fs.extend_from_slice(fs_bytes);
+        Ok(())
+    };
+
+    for (current, (fi, fl)) in fi_entries.iter().zip(&fl_entries).enumerate() {
+        if Some(current) != index {
+            push(
+                fi.clone(),
+                fl,
+                raw_entry_bytes(&streams.fs, fi).with_entry_index(current)?,
+            )?;
+            continue;
+        }
+        if let EntryEdit::Replace {
+            data,
+            compression_type,
+        } = edit
+        {
+            let compression_type = compression_type.unwrap_or(fi.compression_type);
+            push(
+                new_fi(data, compression_type, fl)?,
+                fl,
+                &frame_entry(data, compression_type)?,
+            )?;
+        }
+    }
+
+    if let EntryEdit::Add {
+        data,
+        compression_type,
+        ..
+    } = edit
+    {
+        let fl = FL::from(fl_path.generate_windows_path_with_prefix());
+        push(
+            new_fi(data, *compression_type, &fl)?,
+            &fl,
+            &frame_entry(data, *compression_type)?,
+        )?;
+    }
+
+    Ok(result)
+}
+
+fn new_fi(data: &[u8], compression_type: CompressionTypeT, fl: &FL) -> ArchiveResult<FI> {
+    Ok(FI {
+        uncompressed_size: u32::try_from(data.len()).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is too large for a FI entry", fl),
+            )
+        })?,
+        offset: 0,
+        compression_type,
+    })
+}
+
+fn contains_fl_path(archive: &FIFLFSZZZ, fl_path: &str) -> ArchiveResult<bool> {
+    let target = normalize_archive_path(fl_path);
+    let loaded;
+    let fl_file = match archive.fl_file.as_ref() {
+        Some(fl_file) => fl_file,
+        None => {
+            loaded = FLfile::from_zzz_entry_and_file(&archive.fl, archive.fl_file_path())?;
+            &loaded
+        }
+    };
+    Ok(fl_file
+        .entries
+        .iter()
+        .any(|fl| normalize_archive_path(fl) == target))
+}
+
+/// Applies `edit` to `archive` or to one of its `field_archives`. A change to a nested archive
+/// is written back into this archive's fs, so the returned streams are always this archive's.
+/// Returns `None` when neither holds `fl_path`.
+pub fn edit_fiflfs_archive(
+    archive: &FIFLFSZZZ,
+    fl_path: &str,
+    edit: &EntryEdit,
+) -> ArchiveResult<Option<FIFLFSStreams>> {
+    let is_target = match edit {
+        EntryEdit::Add { archive: fs, .. } => {
+            normalize_archive_path(fs) == normalize_archive_path(&archive.fs.string_data)
+        }
+        _ => contains_fl_path(archive, fl_path)?,
+    };
+    if is_target {
+        let streams = read_fiflfs_streams(archive)?;
+        return edit_fiflfs_streams(&streams, fl_path, edit).map(Some);
+    }
+
+    for child in archive.field_archives.iter().flatten() {
+        if let Some(mut child_streams) = edit_fiflfs_archive(child, fl_path, edit)? {
+            let mut streams = read_fiflfs_streams(archive)?;
+            for (entry, data) in [
+                (&child.fi, std::mem::take(&mut child_streams.fi)),
+                (&child.fl, std::mem::take(&mut child_streams.fl)),
+                (&child.fs, std::mem::take(&mut child_streams.fs)),
+            ] {
+                let replace = EntryEdit::Replace {
+                    data,
+                    compression_type: None,
+                };
+                streams = edit_fiflfs_streams(&streams, &entry.string_data, &replace)?;
+            }
+            return Ok(Some(streams));
+        }
+    }
+    Ok(None)
+}
+
+/// Writes a copy of the zzz to `output_path` with `edit` applied to the FIFLFS entry
+/// `fl_path`. The fi/fl/fs of the affected archive are replaced, every other ZZZ entry is
+/// copied as is. Returns `None` when no archive in this zzz holds `fl_path`.
+pub fn edit_zzz(
+    zzz: &ZZZHeader,
+    fl_path: &str,
+    edit: &EntryEdit,
+    output_path: &str,
+) -> ArchiveResult<Option<ZZZHeader>> {
+    if let (Ok(source), Ok(output)) = (
+        fs::canonicalize(&zzz.file_path),
+        fs::canonicalize(output_path),
+    ) {
+        if source == output {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "The rebuilt archive can not overwrite the archive it is read from",
+            )
+            .into());
+        }
+    }
+
+    for archive in zzz.fiflfs_files.iter().flatten() {
+        let Some(mut streams) = edit_fiflfs_archive(archive, fl_path, edit)? else {
+            continue;
+        };
+        if archive.loose_files.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!(
+                    "{} is a loose archive, write the streams from edit_fiflfs_archive instead",
+                    archive.fs_file_path()
+                ),
+            )
+            .into());
+        }
+
+        let mut zzz_writer = ZZZWriter::new();
+        for entry in &zzz.entries {
+            let string_data = &entry.string_data;
+            if *string_data == archive.fi.string_data {
+                zzz_writer.add_bytes(string_data, std::mem::take(&mut streams.fi))?;
+            } else if *string_data == archive.fl.string_data {
+                zzz_writer.add_bytes(string_data, std::mem::take(&mut streams.fl))?;
+            } else if *string_data == archive.fs.string_data {
+                zzz_writer.add_bytes(string_data, std::mem::take(&mut streams.fs))?;
+            } else {
+                zzz_writer.add_file_range(
+                    string_data,
+                    &zzz.file_path,
+                    entry.file_offset,
+                    entry.file_size as u64,
+                )?;
+            }
+        }
+        return Ok(Some(zzz_writer.write_to_file(output_path)?));
+    }
+    Ok(None)
+}
+
+/// Applies `edit` to the archive of the install holding `fl_path` and writes the rebuilt archive
+/// under `output_directory`: the whole `.zzz` for the Remaster, or the fi, fl and fs under their
+/// path in the install for loose archives. Returns the files written, or `None` when no archive
+/// holds `fl_path`, or for [`EntryEdit::Add`] none has its fs.
+pub fn edit_install(
+    zzz_files: &ZZZfiles,
+    fl_path: &str,
+    edit: &EntryEdit,
+    output_directory: &Path,
+) -> ArchiveResult<Option<Vec<PathBuf>>> {
+    for zzz in zzz_files.into_iter().flatten() {
+        let loose_archives = zzz
+            .fiflfs_files
+            .iter()
+            .flatten()
+            .filter(|archive| archive.loose_files.is_some());
+        for archive in loose_archives {
+            let Some(streams) = edit_fiflfs_archive(archive, fl_path, edit)? else {
+                continue;
+            };
+            let fs_path = output_directory.join(archive.fs.string_data.replace('\\', "/"));
+            fs_path.create_directories()?;
+            let mut written = Vec::new();
+            for (extension, data) in [
+                ("fi", &streams.fi),
+                ("fl", &streams.fl),
+                ("fs", &streams.fs),
+            ] {
+                let path = fs_path.with_extension(extension);
+                fs::write(&path, data)?;
+                written.push(path);
+            }
+            return Ok(Some(written));
+        }
+        if zzz
+            .fiflfs_files
+            .iter()
+            .flatten()
+            .all(|archive| archive.loose_files.is_some())
+        {
+            continue;
+        }
+
+        let Some(file_name) = Path::new(&zzz.file_path).file_name() else {
+            continue;
+        };
+        let output_path = output_directory.join(file_name);
+        if edit_zzz(zzz, fl_path, edit, &output_path.to_string_lossy())?.is_some() {
+            return Ok(Some(vec![output_path]));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fiflfs_writer::FIFLFSWriter;
+    use crate::oviiirs_archive::{
+        find_archives, find_archives_field, find_loose_archives, lz4_decompress_framed,
+        read_bytes_from_memory, ArchiveType,
+    };
+    use crate::test_util::TempDir;
+
+    fn read_back(streams: &FIFLFSStreams, fl_path: &str) -> Option<Vec<u8>> {
+        let fi_entries = parse_fi_entries(&streams.fi).unwrap();
+        let fl_entries = parse_fl_entries(&streams.fl).unwrap();
+        let (fi, _) = fi_entries
+            .iter()
+            .zip(&fl_entries)
+            .find(|(_, fl)| fl.as_str() == fl_path)?;
+        let start = fi.offset as usize;
+        Some(match fi.compression_type {
+            CompressionTypeT::None => {
+                read_bytes_from_memory(&streams.fs, start, fi.uncompressed_size as usize).unwrap()
+            }
+            CompressionTypeT::Lzss => {
+                let size =
+                    u32::from_le_bytes(streams.fs[start..start + 4].try_into().unwrap()) as usize;
+                crate::lzss::decompress(
+                    &streams.fs[start + 4..start + 4 + size],
+                    fi.uncompressed_size as usize,
+                )
+            }
+            CompressionTypeT::Lz4 => {
+                let size =
+                    u32::from_le_bytes(streams.fs[start..start + 4].try_into().unwrap()) as usize;
+                lz4_decompress_framed(&streams.fs[start..start + 4 + size], fi.uncompressed_size)
+                    .unwrap()
+            }
+        })
+    }
+
+    #[test]
+    fn test_edit_fiflfs_streams() {
+        let mut fiflfs_writer = FIFLFSWriter::new();
+        for (name, compression_type) in [
+            ("a", CompressionTypeT::None),
+            ("b", CompressionTypeT::Lzss),
+            ("c", CompressionTypeT::Lz4),
+        ] {
+            fiflfs_writer
+                .add_bytes(
+                    &format!("c:\\ff8\\data\\eng\\{}", name),
+                    name.repeat(100).into_bytes(),
+                    compression_type,
+                )
+                .unwrap();
+        }
+        let streams = fiflfs_writer.to_streams().unwrap();
+
+        let replaced = edit_fiflfs_streams(
+            &streams,
+            "FF8/DATA/ENG/B",
+            &EntryEdit::Replace {
+                data: b"replacement".to_vec(),
+                compression_type: None,
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            read_back(&replaced, "c:\\ff8\\data\\eng\\b").unwrap(),
+            b"replacement"
+        );
+        assert_eq!(
+            read_back(&replaced, "c:\\ff8\\data\\eng\\a").unwrap(),
+            "a".repeat(100).into_bytes()
+        );
+        assert_eq!(
+            read_back(&replaced, "c:\\ff8\\data\\eng\\c").unwrap(),
+            "c".repeat(100).into_bytes()
+        );
+        // The untouched lz4 entry is copied byte for byte.
+        let lz4_fi = &parse_fi_entries(&streams.fi).unwrap()[2];
+        let lz4_frame = raw_entry_bytes(&streams.fs, lz4_fi).unwrap();
+        assert!(replaced.fs.ends_with(lz4_frame));
+
+        let removed =
+            edit_fiflfs_streams(&replaced, "c:\\ff8\\data\\eng\\a", &EntryEdit::Remove).unwrap();
+        assert!(read_back(&removed, "c:\\ff8\\data\\eng\\a").is_none());
+        assert_eq!(
+            read_back(&removed, "c:\\ff8\\data\\eng\\c").unwrap(),
+            "c".repeat(100).into_bytes()
+        );
+
+        let added = edit_fiflfs_streams(
+            &removed,
+            "ff8\\data\\eng\\d",
+            &EntryEdit::Add {
+                archive: String::new(),
+                data: b"new".to_vec(),
+                compression_type: CompressionTypeT::Lzss,
+            },
+        )
+        .unwrap();
+        assert_eq!(read_back(&added, "c:\\ff8\\data\\eng\\d").unwrap(), b"new");
+        assert!(
+            edit_fiflfs_streams(&added, "c:\\ff8\\data\\eng\\zzz", &EntryEdit::Remove).is_err()
+        );
+    }
+
+    #[test]
+    fn test_edit_zzz_nested_field_archive() {
+        let directory = TempDir::new("edit");
+        let source_path = directory.join("main.zzz").to_str().unwrap().to_string();
+        let output_path = directory.join("edited.zzz").to_str().unwrap().to_string();
+
+        let mut child_writer = FIFLFSWriter::new();
+        child_writer
+            .add_bytes(
+                "c:\\ff8\\data\\eng\\field\\mapdata\\test\\test.msd",
+                b"old dialog".to_vec(),
+                CompressionTypeT::Lzss,
+            )
+            .unwrap();
+        let child = child_writer.to_streams().unwrap();
+
+        let mut field_writer = FIFLFSWriter::new();
+        for (extension, data, compression_type) in [
+            ("fi", child.fi, CompressionTypeT::None),
+            ("fl", child.fl, CompressionTypeT::None),
+            ("fs", child.fs, CompressionTypeT::Lzss),
+        ] {
+            field_writer
+                .add_bytes(
+                    &format!("c:\\ff8\\data\\eng\\field\\mapdata\\test.{}", extension),
+                    data,
+                    compression_type,
+                )
+                .unwrap();
+        }
+        let mut zzz_writer = ZZZWriter::new();
+        zzz_writer
+            .add_bytes("data\\other.bin", b"untouched".to_vec())
+            .unwrap();
+        field_writer
+            .add_to_zzz(&mut zzz_writer, "data\\lang-en\\field")
+            .unwrap();
+        zzz_writer.write_to_file(&source_path).unwrap();
+
+        let load = |path: &String| {
+            let mut zzz = crate::oviiirs_archive::read_data_from_file(path).unwrap();
+            let mut archives = find_archives(zzz.entries.clone(), path);
+            let field = archives
+                .iter_mut()
+                .find(|archive| archive.archive_type == ArchiveType::Field)
+                .unwrap();
+            field.field_archives = Some(find_archives_field(field).unwrap());
+            zzz.fiflfs_files = Some(archives);
+            zzz
+        };
+
+        let edited = edit_zzz(
+            &load(&source_path),
+            "c:\\ff8\\data\\eng\\field\\mapdata\\test\\test.msd",
+            &EntryEdit::Replace {
+                data: b"a much longer line of new dialog".to_vec(),
+                compression_type: None,
+            },
+            &output_path,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(edited.count, 4);
+
+        let edited = load(&output_path);
+        let field = &edited.fiflfs_files.as_ref().unwrap()[0];
+        let nested = &field.field_archives.as_ref().unwrap()[0];
+        let nested_streams = read_fiflfs_streams(nested).unwrap();
+        assert_eq!(
+            read_back(
+                &nested_streams,
+                "c:\\ff8\\data\\eng\\field\\mapdata\\test\\test.msd"
+            )
+            .unwrap(),
+            b"a much longer line of new dialog"
+        );
+        let other = &edited.entries[0];
+        assert_eq!(
+            crate::oviiirs_archive::read_bytes_from_file(
+                &ArchiveReader::open(&output_path).unwrap(),
+                other.file_offset,
+                other.file_size as u64
+            )
+            .unwrap(),
+            b"untouched"
+        );
+        assert!(
+            edit_zzz(&edited, "c:\\missing", &EntryEdit::Remove, &source_path)
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_edit_install_loose_archive() {
+        let directory = TempDir::new("edit_install");
+        let install = directory.join("install");
+        let output = directory.join("output");
+        fs::create_dir_all(install.join("Data")).unwrap();
+        fs::create_dir_all(&output).unwrap();
+
+        let mut fiflfs_writer = FIFLFSWriter::new();
+        fiflfs_writer
+            .add_bytes(
+                "c:\\ff8\\data\\eng\\menu\\a.txt",
+                b"old".to_vec(),
+                CompressionTypeT::Lzss,
+            )
+            .unwrap();
+        let streams = fiflfs_writer.to_streams().unwrap();
+        for (extension, data) in [
+            ("fi", &streams.fi),
+            ("fl", &streams.fl),
+            ("fs", &streams.fs),
+        ] {
+            fs::write(
+                install.join("Data").join(format!("menu.{}", extension)),
+                data,
+            )
+            .unwrap();
+        }
+
+        let install_path = install.to_str().unwrap().to_string();
+        let mut archives = find_loose_archives(&install_path).unwrap();
+        for archive in archives.iter_mut() {
+            archive.fi_file =
+                Some(FIfile::from_zzz_entry_and_file(&archive.fi, archive.fi_file_path()).unwrap());
+            archive.fl_file =
+                Some(FLfile::from_zzz_entry_and_file(&archive.fl, archive.fl_file_path()).unwrap());
+        }
+        let zzz_files = ZZZfiles {
+            loose: Some(ZZZHeader {
+                file_path: install_path,
+                fiflfs_files: Some(archives),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let written = edit_install(
+            &zzz_files,
+            "c:\\ff8\\data\\eng\\menu\\a.txt",
+            &EntryEdit::Replace {
+                data: b"new".to_vec(),
+                compression_type: None,
+            },
+            &output,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(written.len(), 3);
+        let edited = FIFLFSStreams {
+            fi: fs::read(&written[0]).unwrap(),
+            fl: fs::read(&written[1]).unwrap(),
+            fs: fs::read(&written[2]).unwrap(),
+        };
+        assert_eq!(
+            read_back(&edited, "c:\\ff8\\data\\eng\\menu\\a.txt").unwrap(),
+            b"new"
+        );
+        assert!(
+            edit_install(&zzz_files, "c:\\missing", &EntryEdit::Remove, &output)
+                .unwrap()
+                .is_none()
+        );
+    }
+}
@@ -0,0 +1,336 @@
+use crate::error::{ArchiveError, ArchiveResult, ErrorLocation, ResultExt};
+use crate::lzss::LzssReader;
+use crate::oviiirs_archive::{
+    read_compressed_bytes_from_reader_at_offset_lz4,
+    read_compressed_bytes_from_reader_at_offset_lzss, read_entry_bytes_from_reader,
+    CompressionTypeT, ZZZEntry,
+};
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+
+const COPY_BUFFER_SIZE: usize = 64 * 1024;
+
+// Platforms without positional reads seek a file shared behind a lock instead.
+#[cfg(any(unix, windows))]
+type SharedFile = File;
+#[cfg(not(any(unix, windows)))]
+type SharedFile = std::sync::Mutex<File>;
+
+#[derive(Debug)]
+enum Backing {
+    File(SharedFile),
+    #[cfg(feature = "mmap")]
+    Mmap(memmap2::Mmap),
+}
+
+/// An opened archive file. Reads are positional, they never move a shared cursor, so one reader
+/// can be used for every entry of a `.zzz` and shared between threads.
+#[derive(Debug)]
+pub struct ArchiveReader {
+    file_path: String,
+    len: u64,
+    backing: Backing,
+}
+
+impl ArchiveReader {
+    pub fn open(file_path: &str) -> ArchiveResult<Self> {
+        let file = File::open(file_path).with_path(file_path)?;
+        Ok(ArchiveReader {
+            file_path: file_path.to_string(),
+            len: file.metadata().with_path(file_path)?.len(),
+            backing: Backing::File(SharedFile::from(file)),
+        })
+    }
+
+    /// Maps the whole file into memory instead of reading it through the file handle. The file
+    /// must not be modified while the reader is alive.
+    #[cfg(feature = "mmap")]
+    pub fn open_mmap(file_path: &str) -> ArchiveResult<Self> {
+        let file = File::open(file_path).with_path(file_path)?;
+        // SAFETY: the game files are only read here, changing them underneath a running extract
+        // is not supported.
+        let mmap = unsafe { memmap2::Mmap::map(&file) }.with_path(file_path)?;
+        Ok(ArchiveReader {
+            file_path: file_path.to_string(),
+            len: mmap.len() as u64,
+            backing: Backing::Mmap(mmap),
+        })
+    }
+
+    pub fn file_path(&self) -> &str {
+        &self.file_path
+    }
+
+    /// The size of the file when it was opened.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// A `Read + Seek` view with its own position, for the parsers that take a reader.
+    pub fn cursor(&self) -> ArchiveCursor<'_> {
+        ArchiveCursor {
+            reader: self,
+            position: 0,
+        }
+    }
+
+    fn check_bounds(&self, offset: u64, size: u64) -> ArchiveResult<()> {
+        if offset.checked_add(size).is_none_or(|end| end > self.len) {
+            return Err(ArchiveError::EntryOutOfBounds {
+                location: ErrorLocation::default(),
+                size,
+                available: self.len.saturating_sub(offset),
+            }
+            .with_offset(offset)
+            .with_path(&self.file_path));
+        }
+        Ok(())
+    }
+
+    /// Fills `buffer` with the bytes starting at `offset`.
+    pub fn read_exact_at(&self, buffer: &mut [u8], offset: u64) -> ArchiveResult<()> {
+        self.check_bounds(offset, buffer.len() as u64)?;
+        match &self.backing {
+            Backing::File(file) => read_exact_at(file, buffer, offset)
+                .with_offset(offset)
+                .with_path(&self.file_path),
+            #[cfg(feature = "mmap")]
+            Backing::Mmap(mmap) => {
+                let start = offset as usize;
+                buffer.copy_from_slice(&mmap[start..start + buffer.len()]);
+                Ok(())
+            }
+        }
+    }
+
+    pub fn read_bytes(&self, offset: u64, size: u64) -> ArchiveResult<Vec<u8>> {
+        // Checked before allocating so a damaged size can not ask for gigabytes.
+        self.check_bounds(offset, size)?;
+        let mut buffer = vec![0u8; size as usize];
+        self.read_exact_at(&mut buffer, offset)?;
+        Ok(buffer)
+    }
+
+    /// The LZSS block at `offset`, without its u32 size prefix.
+    pub fn read_compressed_bytes_lzss(&self, offset: u64) -> ArchiveResult<Vec<u8>> {
+        read_compressed_bytes_from_reader_at_offset_lzss(&mut self.cursor(), offset)
+            .with_path(&self.file_path)
+    }
+
+    /// The LZ4 block at `offset`, without the size, marker and uncompressed size that precede it.
+    pub fn read_compressed_bytes_lz4(&self, offset: u64) -> ArchiveResult<Vec<u8>> {
+        read_compressed_bytes_from_reader_at_offset_lz4(&mut self.cursor(), offset)
+            .with_path(&self.file_path)
+    }
+
+    /// Decompresses the LZSS block at `offset` as it is read instead of loading it first. Reading
+    /// fails unless the block decompresses to exactly `uncompressed_size` bytes.
+    pub fn lzss_reader(
+        &self,
+        offset: u64,
+        uncompressed_size: u64,
+    ) -> ArchiveResult<LzssReader<io::Take<ArchiveCursor<'_>>>> {
+        let mut size = [0u8; 4];
+        self.read_exact_at(&mut size, offset)?;
+        let size = u32::from_le_bytes(size) as u64;
+        self.check_bounds(offset + 4, size)?;
+        let mut cursor = self.cursor();
+        cursor.position = offset + 4;
+        Ok(LzssReader::strict(cursor.take(size), uncompressed_size))
+    }
+
+    /// Reads the data stored at `offset`, decompressing it to `uncompressed_size` bytes.
+    pub fn read_entry_bytes_at(
+        &self,
+        offset: u64,
+        uncompressed_size: u32,
+        compression_type: CompressionTypeT,
+    ) -> ArchiveResult<Vec<u8>> {
+        self.read_entry_bytes(&ZZZEntry {
+            file_offset: offset,
+            file_size: uncompressed_size,
+            compression_type,
+            ..Default::default()
+        })
+    }
+
+    pub fn read_entry_bytes(&self, entry: &ZZZEntry) -> ArchiveResult<Vec<u8>> {
+        read_entry_bytes_from_reader(entry, &mut self.cursor()).with_path(&self.file_path)
+    }
+
+    /// Copies `size` bytes starting at `offset` into `writer` through a fixed size buffer.
+    pub fn copy_range<W: Write>(
+        &self,
+        offset: u64,
+        size: u64,
+        writer: &mut W,
+    ) -> ArchiveResult<u64> {
+        self.check_bounds(offset, size)?;
+        let mut buffer = vec![0u8; COPY_BUFFER_SIZE.min(size as usize)];
+        let mut copied = 0u64;
+        while copied < size {
+            let chunk = &mut buffer[..(size - copied).min(COPY_BUFFER_SIZE as u64) as usize];
+            self.read_exact_at(chunk, offset + copied)?;
+            writer.write_all(chunk)?;
+            copied += chunk.len() as u64;
+        }
+        Ok(copied)
+    }
+}
+
+/// See [`ArchiveReader::cursor`].
+#[derive(Debug, Clone)]
+pub struct ArchiveCursor<'a> {
+    reader: &'a ArchiveReader,
+    position: u64,
+}
+
+impl Read for ArchiveCursor<'_> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.reader.len.saturating_sub(self.position);
+        let size = (buffer.len() as u64).min(remaining) as usize;
+        self.reader
+            .read_exact_at(&mut buffer[..size], self.position)?;
+        self.position += size as u64;
+        Ok(size)
+    }
+}
+
+impl Seek for ArchiveCursor<'_> {
+    fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+        let (base, offset) = match position {
+            SeekFrom::Start(offset) => {
+                self.position = offset;
+                return Ok(offset);
+            }
+            SeekFrom::End(offset) => (self.reader.len, offset),
+            SeekFrom::Current(offset) => (self.position, offset),
+        };
+        self.position = base.checked_add_signed(offset).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Invalid seek to a negative or overflowing position",
+            )
+        })?;
+        Ok(self.position)
+    }
+}
+
+#[cfg(unix)]
+fn read_exact_at(file: &File, buffer: &mut [u8], offset: u64) -> io::Result<()> {
+    std::os::unix::fs::FileExt::read_exact_at(file, buffer, offset)
+}
+
+#[cfg(windows)]
+fn read_exact_at(file: &File, mut buffer: &mut [u8], mut offset: u64) -> io::Result<()> {
+    use std::os::windows::fs::FileExt;
+    while !buffer.is_empty() {
+        match file.seek_read(buffer, offset) {
+            Ok(0) => return Err(io::Error::from(io::ErrorKind::UnexpectedEof)),
+            Ok(read) => {
+                buffer = &mut buffer[read..];
+                offset += read as u64;
+            }
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(any(unix, windows)))]
+fn read_exact_at(file: &std::sync::Mutex<File>, buffer: &mut [u8], offset: u64) -> io::Result<()> {
+    let mut file = file
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner);
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(buffer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fiflfs_writer::frame_entry;
+    use crate::test_util::TempDir;
+    use std::fs;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn test_archive_reader_positional_reads() {
+        assert_send_sync::<ArchiveReader>();
+
+        let directory = TempDir::new("archive_reader");
+        let file_path = directory.join("archive.bin");
+        let file_path = file_path.to_str().unwrap();
+
+        let plain = b"uncompressed".repeat(10000);
+        let lzss = frame_entry(&plain, CompressionTypeT::Lzss).unwrap();
+        let lz4 = frame_entry(&plain, CompressionTypeT::Lz4).unwrap();
+        let mut bytes = plain.clone();
+        bytes.extend_from_slice(&lzss);
+        bytes.extend_from_slice(&lz4);
+        fs::write(file_path, &bytes).unwrap();
+
+        let readers = vec![
+            ArchiveReader::open(file_path).unwrap(),
+            #[cfg(feature = "mmap")]
+            ArchiveReader::open_mmap(file_path).unwrap(),
+        ];
+        for reader in &readers {
+            assert_eq!(reader.len(), bytes.len() as u64);
+            let size = plain.len() as u64;
+            for (offset, compression_type) in [
+                (0, CompressionTypeT::None),
+                (size, CompressionTypeT::Lzss),
+                (size + lzss.len() as u64, CompressionTypeT::Lz4),
+            ] {
+                assert_eq!(
+                    reader
+                        .read_entry_bytes_at(offset, size as u32, compression_type)
+                        .unwrap(),
+                    plain
+                );
+            }
+            let mut streamed = Vec::new();
+            reader
+                .lzss_reader(size, size)
+                .unwrap()
+                .read_to_end(&mut streamed)
+                .unwrap();
+            assert_eq!(streamed, plain);
+            let err = reader
+                .lzss_reader(size, size + 1)
+                .unwrap()
+                .read_to_end(&mut streamed)
+                .unwrap_err();
+            assert!(ArchiveError::from(err).is_corrupt());
+            assert!(reader.lzss_reader(bytes.len() as u64 - 2, 1).is_err());
+            let mut copied = Vec::new();
+            assert_eq!(
+                reader.copy_range(3, size - 3, &mut copied).unwrap(),
+                size - 3
+            );
+            assert_eq!(copied, &plain[3..]);
+            assert!(reader.read_bytes(bytes.len() as u64 - 2, 4).is_err());
+        }
+
+        // Positional reads do not share a cursor, so threads can use one reader.
+        let reader = &readers[0];
+        std::thread::scope(|scope| {
+            for start in [0u64, 12, 24, 36] {
+                scope.spawn(move || {
+                    assert_eq!(reader.read_bytes(start, 12).unwrap(), b"uncompressed");
+                });
+            }
+        });
+    }
+}
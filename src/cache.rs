@@ -0,0 +1,480 @@
+use crate::manifest::modified_nanos;
+use crate::oviiirs_archive::{
+    find_loose_archives, process_files_in_directory, read_data_from_file, write_file_atomically,
+    ArchiveType, FIfile, FLfile, LanguageCode, ZZZArchiveType, ZZZEntry, ZZZHeader, ZZZfiles,
+    FIFLFSZZZ,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Bumped whenever the layout of [`ArchiveCache`] or of anything it holds, like `ZZZHeader` and
+/// `FIFLFSZZZ`, changes, so caches written by another version are scanned again instead of
+/// being misread.
+pub const CACHE_SCHEMA_VERSION: u32 = 1;
+
+// Starts every cache file, followed by the schema version as a little endian u32.
+const CACHE_MAGIC: &[u8; 8] = b"OVIIIRSC";
+
+// Bytes at the start of each archive that are hashed, enough to hold the header of a .zzz.
+const HEADER_HASH_SIZE: u64 = 64 * 1024;
+
+/// Identifies the contents of an archive on disk without reading all of it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileFingerprint {
+    pub size: u64,
+    /// Modification time in nanoseconds since the Unix epoch.
+    pub modified: u64,
+    /// CRC32 of the first 64 KiB.
+    pub header_crc32: u32,
+}
+
+impl FileFingerprint {
+    pub fn new(path: &str) -> io::Result<Self> {
+        let metadata = fs::metadata(path)?;
+        let mut header = Vec::new();
+        File::open(path)?
+            .take(HEADER_HASH_SIZE)
+            .read_to_end(&mut header)?;
+        Ok(FileFingerprint {
+            size: metadata.len(),
+            modified: modified_nanos(&metadata),
+            header_crc32: crc32fast::hash(&header),
+        })
+    }
+}
+
+/// The scanned archives of an install, with what is needed to tell whether they still match it.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ArchiveCache {
+    #[serde(default)]
+    pub schema_version: u32,
+    #[serde(default)]
+    pub install_directory: String,
+    /// Keyed by the path of each `.zzz` in the install directory.
+    #[serde(default)]
+    pub zzz: BTreeMap<String, FileFingerprint>,
+    /// Keyed by the path of each loose fi, fl and fs of the 2000 and Steam 2013 releases.
+    #[serde(default)]
+    pub loose: BTreeMap<String, FileFingerprint>,
+    #[serde(default)]
+    pub zzz_files: ZZZfiles,
+}
+
+/// The outcome of [`ArchiveCache::load`].
+#[derive(Debug)]
+pub enum CacheLoad {
+    Loaded(Box<ArchiveCache>),
+    Missing,
+    /// The file is damaged or was written by another version. It should be replaced by a new
+    /// scan.
+    Discarded(String),
+}
+
+/// What has to be scanned again for an [`ArchiveCache`] to match the install.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CacheStatus {
+    Valid,
+    /// Some archives were changed, added or removed since the cache was written.
+    Stale {
+        /// `.zzz` files to load again, or to drop when they no longer exist.
+        zzz_paths: Vec<String>,
+        /// Whether the loose archives have to be loaded again.
+        loose: bool,
+    },
+    /// The cache belongs to another install or another schema version and is of no use.
+    Invalid(String),
+}
+
+impl ArchiveCache {
+    /// Fingerprints the archives `zzz_files` was loaded from.
+    pub fn new(install_directory: &str, zzz_files: ZZZfiles) -> io::Result<Self> {
+        let mut zzz = BTreeMap::new();
+        for path in process_files_in_directory(&install_directory.to_string())? {
+            let fingerprint = FileFingerprint::new(&path)?;
+            zzz.insert(path, fingerprint);
+        }
+        Ok(ArchiveCache {
+            schema_version: CACHE_SCHEMA_VERSION,
+            install_directory: install_directory.to_string(),
+            zzz,
+            loose: fingerprint_loose_archives(install_directory)?,
+            zzz_files,
+        })
+    }
+
+    /// Where the cache of `install_directory` is kept inside `cache_directory`, named after a
+    /// hash of the install path so every install has its own.
+    pub fn path_for_install(cache_directory: &Path, install_directory: &str) -> PathBuf {
+        let mut name = String::with_capacity(20);
+        for byte in &Sha256::digest(install_directory.as_bytes())[..8] {
+            write!(name, "{:02x}", byte).unwrap();
+        }
+        cache_directory.join(format!("{}.bin", name))
+    }
+
+    pub fn load(path: &Path) -> io::Result<CacheLoad> {
+        let contents = match fs::read(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(CacheLoad::Missing),
+            Err(err) => return Err(err),
+        };
+        let Some(payload) = contents.strip_prefix(CACHE_MAGIC.as_slice()) else {
+            return Ok(CacheLoad::Discarded(
+                "the cache has no header, it was written by an older version".to_string(),
+            ));
+        };
+        let (Some(version), payload) = (
+            payload
+                .get(..4)
+                .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap())),
+            payload.get(4..).unwrap_or_default(),
+        ) else {
+            return Ok(CacheLoad::Discarded(
+                "the cache header is truncated".to_string(),
+            ));
+        };
+        if version != CACHE_SCHEMA_VERSION {
+            return Ok(CacheLoad::Discarded(format!(
+                "cache schema version {} does not match {}",
+                version, CACHE_SCHEMA_VERSION
+            )));
+        }
+        match bincode::deserialize::<ArchiveCache>(payload) {
+            Ok(cache) => Ok(CacheLoad::Loaded(Box::new(cache))),
+            Err(err) => Ok(CacheLoad::Discarded(format!(
+                "the cache is damaged: {}",
+                err
+            ))),
+        }
+    }
+
+    /// Imports the cache older versions kept in `cache/archives.bin` of the working directory, a
+    /// bare `ZZZfiles` shared by every install. Only the `.zzz` files of `install_directory`
+    /// whose entries still match the file on disk are kept, and the loose archives it did not
+    /// know about are left out, so [`ArchiveCache::status`] has the rest scanned again. `None`
+    /// when the file cannot be read as such a cache.
+    pub fn load_legacy(path: &Path, install_directory: &str) -> io::Result<Option<Self>> {
+        let Ok(legacy) = bincode::deserialize::<LegacyZZZfiles>(&fs::read(path)?) else {
+            return Ok(None);
+        };
+        let mut cache = ArchiveCache {
+            schema_version: CACHE_SCHEMA_VERSION,
+            install_directory: install_directory.to_string(),
+            ..Default::default()
+        };
+        let zzz_paths = process_files_in_directory(&install_directory.to_string())?;
+        for header in [legacy.main, legacy.other].into_iter().flatten() {
+            let header = ZZZHeader::from(header);
+            if !zzz_paths.contains(&header.file_path)
+                || read_data_from_file(&header.file_path)?.entries != header.entries
+            {
+                continue;
+            }
+            let fingerprint = FileFingerprint::new(&header.file_path)?;
+            cache.zzz.insert(header.file_path.clone(), fingerprint);
+            cache.zzz_files.push(header);
+        }
+        Ok(Some(cache))
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let payload = bincode::serialize(self).map_err(|err| {
+            io::Error::other(format!("Failed to serialize the archive cache: {}", err))
+        })?;
+        let mut contents = Vec::with_capacity(CACHE_MAGIC.len() + 4 + payload.len());
+        contents.extend_from_slice(CACHE_MAGIC);
+        contents.extend_from_slice(&CACHE_SCHEMA_VERSION.to_le_bytes());
+        contents.extend_from_slice(&payload);
+        write_file_atomically(path, &contents)
+    }
+
+    /// Compares the fingerprints with the archives currently in `install_directory`.
+    pub fn status(&self, install_directory: &str) -> io::Result<CacheStatus> {
+        if self.schema_version != CACHE_SCHEMA_VERSION {
+            return Ok(CacheStatus::Invalid(format!(
+                "cache schema version {} does not match {}",
+                self.schema_version, CACHE_SCHEMA_VERSION
+            )));
+        }
+        if self.install_directory != install_directory {
+            return Ok(CacheStatus::Invalid(format!(
+                "cache was written for \"{}\"",
+                self.install_directory
+            )));
+        }
+
+        let mut current = BTreeMap::new();
+        for path in process_files_in_directory(&install_directory.to_string())? {
+            let fingerprint = FileFingerprint::new(&path)?;
+            current.insert(path, fingerprint);
+        }
+        let mut zzz_paths: Vec<String> = current
+            .iter()
+            .filter(|(path, fingerprint)| self.zzz.get(*path) != Some(fingerprint))
+            .map(|(path, _)| path.clone())
+            .collect();
+        zzz_paths.extend(
+            self.zzz
+                .keys()
+                .filter(|path| !current.contains_key(*path))
+                .cloned(),
+        );
+        let loose = fingerprint_loose_archives(install_directory)? != self.loose;
+
+        if zzz_paths.is_empty() && !loose {
+            Ok(CacheStatus::Valid)
+        } else {
+            Ok(CacheStatus::Stale { zzz_paths, loose })
+        }
+    }
+}
+
+/// Where the caches are kept: `oviiirs_archive` in the user cache directory, which is
+/// `$XDG_CACHE_HOME` or `~/.cache` on Linux.
+pub fn default_cache_directory() -> Option<PathBuf> {
+    dirs::cache_dir().map(|directory| directory.join("oviiirs_archive"))
+}
+
+// The layout of `cache/archives.bin` from before the versioned cache. bincode stores fields by
+// position, so the fields added since can not just be defaulted.
+#[derive(Debug, Deserialize)]
+#[cfg_attr(test, derive(Serialize))]
+struct LegacyZZZfiles {
+    main: Option<LegacyZZZHeader>,
+    other: Option<LegacyZZZHeader>,
+}
+
+#[derive(Debug, Deserialize)]
+#[cfg_attr(test, derive(Serialize))]
+struct LegacyZZZHeader {
+    file_path: String,
+    archive_type: ZZZArchiveType,
+    count: u32,
+    entries: Vec<ZZZEntry>,
+    fiflfs_files: Option<Vec<LegacyFIFLFSZZZ>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[cfg_attr(test, derive(Serialize))]
+struct LegacyFIFLFSZZZ {
+    file_path: String,
+    language: LanguageCode,
+    archive_type: ArchiveType,
+    fi: ZZZEntry,
+    fl: ZZZEntry,
+    fs: ZZZEntry,
+    fi_file: Option<FIfile>,
+    fl_file: Option<FLfile>,
+    field_archives: Option<Vec<LegacyFIFLFSZZZ>>,
+}
+
+impl From<LegacyZZZHeader> for ZZZHeader {
+    fn from(legacy: LegacyZZZHeader) -> Self {
+        ZZZHeader {
+            file_path: legacy.file_path,
+            archive_type: legacy.archive_type,
+            count: legacy.count,
+            entries: legacy.entries,
+            fiflfs_files: legacy
+                .fiflfs_files
+                .map(|archives| archives.into_iter().map(FIFLFSZZZ::from).collect()),
+        }
+    }
+}
+
+impl From<LegacyFIFLFSZZZ> for FIFLFSZZZ {
+    fn from(legacy: LegacyFIFLFSZZZ) -> Self {
+        FIFLFSZZZ {
+            file_path: legacy.file_path,
+            language: legacy.language,
+            archive_type: legacy.archive_type,
+            fi: legacy.fi,
+            fl: legacy.fl,
+            fs: legacy.fs,
+            fi_file: legacy.fi_file,
+            fl_file: legacy.fl_file,
+            field_archives: legacy
+                .field_archives
+                .map(|archives| archives.into_iter().map(FIFLFSZZZ::from).collect()),
+            loose_files: None,
+        }
+    }
+}
+
+fn fingerprint_loose_archives(
+    install_directory: &str,
+) -> io::Result<BTreeMap<String, FileFingerprint>> {
+    let mut fingerprints = BTreeMap::new();
+    for archive in find_loose_archives(&install_directory.to_string())? {
+        for path in [
+            archive.fi_file_path(),
+            archive.fl_file_path(),
+            archive.fs_file_path(),
+        ] {
+            fingerprints.insert(path.to_string(), FileFingerprint::new(path)?);
+        }
+    }
+    Ok(fingerprints)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+    use crate::zzz_writer::ZZZWriter;
+
+    #[test]
+    fn test_archive_cache_status() {
+        let directory = TempDir::new("cache");
+        let install = directory.join("install");
+        fs::create_dir_all(install.join("Data")).unwrap();
+        let install_directory = install.to_str().unwrap().to_string();
+        let main_path = install.join("main.zzz").to_str().unwrap().to_string();
+        fs::write(&main_path, b"main").unwrap();
+        for extension in ["fi", "fl", "fs"] {
+            fs::write(
+                install.join("Data").join(format!("menu.{}", extension)),
+                b"",
+            )
+            .unwrap();
+        }
+
+        let cache = ArchiveCache::new(&install_directory, ZZZfiles::default()).unwrap();
+        assert_eq!(cache.zzz.len(), 1);
+        assert_eq!(cache.loose.len(), 3);
+        let cache_path = ArchiveCache::path_for_install(&directory, &install_directory);
+        cache.save(&cache_path).unwrap();
+        let CacheLoad::Loaded(cache) = ArchiveCache::load(&cache_path).unwrap() else {
+            panic!("the cache was not loaded");
+        };
+        assert_eq!(
+            cache.status(&install_directory).unwrap(),
+            CacheStatus::Valid
+        );
+
+        fs::write(&main_path, b"main.zzz").unwrap();
+        let other_path = install.join("other.zzz").to_str().unwrap().to_string();
+        fs::write(&other_path, b"other").unwrap();
+        assert_eq!(
+            cache.status(&install_directory).unwrap(),
+            CacheStatus::Stale {
+                zzz_paths: vec![main_path.clone(), other_path],
+                loose: false,
+            }
+        );
+
+        fs::write(install.join("Data").join("menu.fs"), b"fs").unwrap();
+        fs::remove_file(&main_path).unwrap();
+        assert!(matches!(
+            cache.status(&install_directory).unwrap(),
+            CacheStatus::Stale { loose: true, ref zzz_paths } if zzz_paths.contains(&main_path)
+        ));
+
+        assert!(matches!(
+            cache.status(directory.to_str().unwrap()).unwrap(),
+            CacheStatus::Invalid(_)
+        ));
+        let outdated = ArchiveCache {
+            schema_version: 0,
+            ..*cache
+        };
+        assert!(matches!(
+            outdated.status(&install_directory).unwrap(),
+            CacheStatus::Invalid(_)
+        ));
+    }
+
+    #[test]
+    fn test_archive_cache_versions() {
+        let directory = TempDir::new("cache_versions");
+        let path = ArchiveCache::path_for_install(&directory, "ff8");
+        assert_ne!(
+            path,
+            ArchiveCache::path_for_install(&directory, "ff8 remastered")
+        );
+        assert!(matches!(
+            ArchiveCache::load(&path).unwrap(),
+            CacheLoad::Missing
+        ));
+
+        // The legacy cache knew main.zzz as it is now and an other.zzz that changed since.
+        let install = directory.join("install");
+        fs::create_dir_all(&install).unwrap();
+        let install_directory = install.to_str().unwrap().to_string();
+        let legacy_header = |name: &str, contents: &[u8]| {
+            let zzz_path = install.join(name).to_str().unwrap().to_string();
+            let mut zzz_writer = ZZZWriter::new();
+            zzz_writer
+                .add_bytes("data\\x.bin", contents.to_vec())
+                .unwrap();
+            let zzz = zzz_writer.write_to_file(&zzz_path).unwrap();
+            LegacyZZZHeader {
+                file_path: zzz.file_path,
+                archive_type: zzz.archive_type,
+                count: zzz.count,
+                entries: zzz.entries,
+                fiflfs_files: Some(Vec::new()),
+            }
+        };
+        let legacy = LegacyZZZfiles {
+            main: Some(legacy_header("main.zzz", b"main")),
+            other: Some(legacy_header("other.zzz", b"other")),
+        };
+        legacy_header("other.zzz", b"other, patched");
+        let legacy_path = directory.join("archives.bin");
+        fs::write(&legacy_path, bincode::serialize(&legacy).unwrap()).unwrap();
+        let imported = ArchiveCache::load_legacy(&legacy_path, &install_directory)
+            .unwrap()
+            .unwrap();
+        assert_eq!(imported.schema_version, CACHE_SCHEMA_VERSION);
+        assert!(imported.zzz_files.main.is_some() && imported.zzz_files.other.is_none());
+        assert_eq!(
+            imported.status(&install_directory).unwrap(),
+            CacheStatus::Stale {
+                zzz_paths: vec![install.join("other.zzz").to_str().unwrap().to_string()],
+                loose: false,
+            }
+        );
+        assert!(matches!(
+            ArchiveCache::load(&legacy_path).unwrap(),
+            CacheLoad::Discarded(_)
+        ));
+        fs::write(&legacy_path, b"not a cache").unwrap();
+        assert!(ArchiveCache::load_legacy(&legacy_path, &install_directory)
+            .unwrap()
+            .is_none());
+
+        let mut future = CACHE_MAGIC.to_vec();
+        future.extend_from_slice(&(CACHE_SCHEMA_VERSION + 1).to_le_bytes());
+        fs::write(&path, &future).unwrap();
+        assert!(matches!(
+            ArchiveCache::load(&path).unwrap(),
+            CacheLoad::Discarded(_)
+        ));
+        future.truncate(CACHE_MAGIC.len() + 2);
+        fs::write(&path, &future).unwrap();
+        assert!(matches!(
+            ArchiveCache::load(&path).unwrap(),
+            CacheLoad::Discarded(_)
+        ));
+
+        imported.save(&path).unwrap();
+        let mut damaged = fs::read(&path).unwrap();
+        damaged.truncate(damaged.len() - 1);
+        fs::write(&path, &damaged).unwrap();
+        assert!(matches!(
+            ArchiveCache::load(&path).unwrap(),
+            CacheLoad::Discarded(_)
+        ));
+    }
+}
@@ -0,0 +1,268 @@
+use crate::error::{ArchiveResult, ResultExt};
+use crate::extract::FileCopier;
+use crate::manifest::MANIFEST_FILE_NAME;
+use crate::oviiirs_archive::{load_toml_from_file, save_toml};
+use crate::plan::ExtractionPlan;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write as _;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileChecksum {
+    pub size: u64,
+    pub crc32: u32,
+    /// Lowercase hex.
+    pub sha256: String,
+}
+
+/// Hashes everything written through it before passing it on to `inner`.
+pub struct ChecksumWriter<W: Write> {
+    inner: W,
+    size: u64,
+    crc32: crc32fast::Hasher,
+    sha256: Option<Sha256>,
+}
+
+impl<W: Write> ChecksumWriter<W> {
+    pub fn new(inner: W) -> Self {
+        ChecksumWriter {
+            inner,
+            size: 0,
+            crc32: crc32fast::Hasher::new(),
+            sha256: Some(Sha256::new()),
+        }
+    }
+
+    /// Skips the SHA-256, for the incremental extraction that only compares CRC32s. The
+    /// checksum it finishes with has an empty `sha256`.
+    pub fn crc32_only(inner: W) -> Self {
+        ChecksumWriter {
+            sha256: None,
+            ..ChecksumWriter::new(inner)
+        }
+    }
+
+    pub fn finish(self) -> FileChecksum {
+        let mut sha256 = String::with_capacity(64);
+        for byte in self.sha256.into_iter().flat_map(|hasher| hasher.finalize()) {
+            write!(sha256, "{:02x}", byte).unwrap();
+        }
+        FileChecksum {
+            size: self.size,
+            crc32: self.crc32.finalize(),
+            sha256,
+        }
+    }
+}
+
+impl<W: Write> Write for ChecksumWriter<W> {
+    fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buffer)?;
+        self.size += written as u64;
+        self.crc32.update(&buffer[..written]);
+        if let Some(sha256) = &mut self.sha256 {
+            sha256.update(&buffer[..written]);
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// The checksums of every file of an extraction, to check an extracted or modded directory
+/// against the game data.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ChecksumManifest {
+    /// Keyed by the path relative to the extract directory, with `/` separators.
+    #[serde(default)]
+    pub files: BTreeMap<String, FileChecksum>,
+}
+
+/// How a directory differs from a [`ChecksumManifest`]. Each list is sorted by path.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChecksumReport {
+    /// In the manifest but not in the directory.
+    pub missing: Vec<String>,
+    /// In the directory but not in the manifest.
+    pub extra: Vec<String>,
+    /// In both, with different contents.
+    pub modified: Vec<String>,
+}
+
+impl ChecksumReport {
+    pub fn is_clean(&self) -> bool {
+        self.missing.is_empty() && self.extra.is_empty() && self.modified.is_empty()
+    }
+}
+
+impl ChecksumManifest {
+    /// Checksums the files of `plan` by decompressing them from the archives, without writing
+    /// anything. Files that go to the same destination keep the checksum of the last one, the
+    /// one an extraction leaves behind.
+    pub fn from_plan(plan: &ExtractionPlan) -> ArchiveResult<Self> {
+        let mut manifest = ChecksumManifest::default();
+        let mut copier = FileCopier::new();
+        for planned in &plan.files {
+            let mut writer = ChecksumWriter::new(io::sink());
+            copier
+                .copy_file(&planned.file, &mut writer)
+                .with_path(planned.file.path)?;
+            manifest
+                .files
+                .insert(plan.relative_path(planned), writer.finish());
+        }
+        Ok(manifest)
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        load_toml_from_file(&path.to_string_lossy().into_owned())
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        save_toml(self, &path.to_string_lossy())
+    }
+
+    /// Compares the files under `directory` with the manifest. The manifest of incremental
+    /// extractions is not counted as an extra file.
+    pub fn verify_directory(&self, directory: &Path) -> io::Result<ChecksumReport> {
+        let mut present = BTreeSet::new();
+        collect_relative_paths(directory, "", &mut present)?;
+        present.remove(MANIFEST_FILE_NAME);
+
+        let mut report = ChecksumReport::default();
+        for (path, checksum) in &self.files {
+            if !present.remove(path.as_str()) {
+                report.missing.push(path.clone());
+                continue;
+            }
+            let file_path = directory.join(path);
+            if fs::metadata(&file_path)?.len() != checksum.size
+                || checksum_file(&file_path)? != *checksum
+            {
+                report.modified.push(path.clone());
+            }
+        }
+        report.extra = present.into_iter().collect();
+        Ok(report)
+    }
+}
+
+pub fn checksum_file(path: &Path) -> io::Result<FileChecksum> {
+    hash_file(path, ChecksumWriter::new(io::sink()))
+}
+
+pub(crate) fn file_crc32(path: &Path) -> io::Result<u32> {
+    Ok(hash_file(path, ChecksumWriter::crc32_only(io::sink()))?.crc32)
+}
+
+fn hash_file(path: &Path, mut writer: ChecksumWriter<io::Sink>) -> io::Result<FileChecksum> {
+    io::copy(&mut File::open(path)?, &mut writer)?;
+    Ok(writer.finish())
+}
+
+fn collect_relative_paths(
+    directory: &Path,
+    prefix: &str,
+    paths: &mut BTreeSet<String>,
+) -> io::Result<()> {
+    for entry in fs::read_dir(directory)? {
+        let entry = entry?;
+        let path = format!("{}{}", prefix, entry.file_name().to_string_lossy());
+        if entry.file_type()?.is_dir() {
+            collect_relative_paths(&entry.path(), &format!("{}/", path), paths)?;
+        } else {
+            paths.insert(path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::oviiirs_archive::{ZZZEntry, ZZZHeader};
+    use crate::plan::PlannedFile;
+    use crate::test_util::TempDir;
+    use crate::vfs::{ArchiveFile, ArchiveFileSource};
+
+    #[test]
+    fn test_checksum_manifest_verify_directory() {
+        let directory = TempDir::new("checksum");
+        let archive_path = directory.join("archive.bin");
+        fs::write(&archive_path, b"first filesecond file").unwrap();
+        let zzz = ZZZHeader {
+            file_path: archive_path.to_str().unwrap().to_string(),
+            entries: vec![
+                ZZZEntry {
+                    string_data: "data\\first.txt".to_string(),
+                    file_offset: 0,
+                    file_size: 10,
+                    ..Default::default()
+                },
+                ZZZEntry {
+                    string_data: "data\\sub\\second.txt".to_string(),
+                    file_offset: 10,
+                    file_size: 11,
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let output = directory.join("out");
+        let plan = ExtractionPlan {
+            extract_directory: output.clone(),
+            files: zzz
+                .entries
+                .iter()
+                .map(|entry| PlannedFile {
+                    file: ArchiveFile {
+                        path: &entry.string_data,
+                        archive_chain: vec![&zzz.file_path],
+                        language: Default::default(),
+                        archive_type: Default::default(),
+                        source: ArchiveFileSource::ZZZ { zzz: &zzz, entry },
+                    },
+                    destination: output.join(entry.string_data.replace('\\', "/")),
+                })
+                .collect(),
+        };
+
+        let manifest = ChecksumManifest::from_plan(&plan).unwrap();
+        assert_eq!(
+            manifest.files["data/first.txt"],
+            FileChecksum {
+                size: 10,
+                crc32: crc32fast::hash(b"first file"),
+                sha256: "bf41cf94047f1a3443ca654a235bc8f830f7997da9b6f3b2b041a866bc6e3b6f"
+                    .to_string(),
+            }
+        );
+        let manifest_path = directory.join("checksums.toml");
+        manifest.save(&manifest_path).unwrap();
+        assert_eq!(ChecksumManifest::load(&manifest_path).unwrap(), manifest);
+
+        plan.extract(|_| {}).unwrap();
+        fs::write(output.join(MANIFEST_FILE_NAME), b"").unwrap();
+        assert!(manifest.verify_directory(&output).unwrap().is_clean());
+
+        fs::write(output.join("data/first.txt"), b"first fil3").unwrap();
+        fs::remove_file(output.join("data/sub/second.txt")).unwrap();
+        fs::write(output.join("data/sub/mod.txt"), b"new").unwrap();
+        assert_eq!(
+            manifest.verify_directory(&output).unwrap(),
+            ChecksumReport {
+                missing: vec!["data/sub/second.txt".to_string()],
+                extra: vec!["data/sub/mod.txt".to_string()],
+                modified: vec!["data/first.txt".to_string()],
+            }
+        );
+    }
+}
@@ -0,0 +1,768 @@
+use clap::{Args, Parser, Subcommand};
+use oviiirs_archive::oviiirs_archive::*;
+use oviiirs_archive::{
+    archive_files, archive_listing, detect_release, edit_install, write_listing, ArchiveError,
+    ArchiveResult, ChecksumManifest, CompressionLevel, EntryEdit, ExtractionPlan, FIFLFSWriter,
+    FileCopier, FileFilter, ListingFormat, ZZZWriter,
+};
+use std::collections::BTreeMap;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use crate::{extract_all_files, load_archives, load_or_rebuild_cache, rebuild_cache};
+
+/// The command failed, the reason is printed to stderr.
+pub const EXIT_FAILURE: u8 = 1;
+// 2 is what clap exits with for invalid arguments.
+/// `verify` found files that could not be read, or a directory that does not match.
+pub const EXIT_DAMAGED: u8 = 3;
+/// `cat`, `replace` or `remove` found no file with that path, or `add` no archive.
+pub const EXIT_NOT_FOUND: u8 = 4;
+
+const EXIT_CODES_HELP: &str = "Exit codes:
+  0  Success
+  1  The command failed
+  2  Invalid arguments
+  3  verify found damaged files or differences
+  4  cat, replace or remove found no file with that path, or add no archive";
+
+/// Extract, inspect and repack the archives of Final Fantasy VIII.
+#[derive(Debug, Parser)]
+#[command(version, after_help = EXIT_CODES_HELP)]
+pub struct Cli {
+    #[command(flatten)]
+    pub overrides: ConfigOverrides,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Settings that take the place of the ones in config.toml for this run. They are not saved.
+#[derive(Debug, Default, Args)]
+pub struct ConfigOverrides {
+    /// The FF8 install directory.
+    #[arg(short = 'd', long, global = true, value_name = "DIR")]
+    pub install_dir: Option<String>,
+    /// The directory files are extracted to.
+    #[arg(short, long, global = true, value_name = "DIR")]
+    pub output_dir: Option<String>,
+    /// Only the archive paths this regular expression matches, empty for every file.
+    #[arg(short, long, global = true, value_name = "REGEX")]
+    pub filter: Option<String>,
+    /// Only the files this query matches, e.g. `lang:fr archive:menu *.tex`. Repeat it to take
+    /// the files any of the queries match. Replaces the include rules of config.toml.
+    #[arg(short, long, global = true, value_name = "QUERY")]
+    pub include: Vec<String>,
+    /// Leave out the files this query matches, e.g. `size>1MB`. Replaces the exclude rules of
+    /// config.toml.
+    #[arg(short = 'x', long, global = true, value_name = "QUERY")]
+    pub exclude: Vec<String>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    #[command(flatten)]
+    Run(RunCommand),
+    /// The interactive menu. This is also what runs when no command is given.
+    Menu,
+}
+
+/// The commands [`run`] carries out without the menu.
+#[derive(Debug, Subcommand)]
+pub enum RunCommand {
+    /// List the files of the install that match the filter.
+    List {
+        /// Also show the size and compression of each file.
+        #[arg(short, long)]
+        long: bool,
+        /// Print the files as json, csv or ndjson with their archives, offsets and sizes.
+        #[arg(long, value_name = "FORMAT", conflicts_with = "long")]
+        format: Option<ListingFormat>,
+    },
+    /// Extract the files that match the filter to the output directory.
+    Extract {
+        /// Print what would be written, the total size and the files that would overwrite each
+        /// other, without writing anything.
+        #[arg(short = 'n', long)]
+        dry_run: bool,
+        /// Only write the files whose entry in the archives or whose output changed since the
+        /// last incremental extract, going by the manifest kept in the output directory.
+        #[arg(short = 'u', long, conflicts_with = "dry_run")]
+        incremental: bool,
+    },
+    /// Summarize the archives of the install.
+    Info,
+    /// Write a file of the install to stdout.
+    Cat {
+        /// Path inside the archives, e.g. `c:\ff8\data\eng\field\mapdata\bc\bccent1\bccent1.msd`.
+        path: String,
+    },
+    /// Pack a directory into a new fi, fl and fs, or a new .zzz.
+    Pack {
+        /// Every file under this directory is added, by its path relative to it.
+        input_dir: PathBuf,
+        /// Path of the archive without extension, `out/field` writes `out/field.fi`,
+        /// `out/field.fl` and `out/field.fs`, or `out/field.zzz` with `--zzz`.
+        output: PathBuf,
+        /// Write a .zzz like the Remaster's main.zzz. Its files are stored uncompressed.
+        #[arg(long, conflicts_with_all = ["compression", "level"])]
+        zzz: bool,
+        /// Compression of the packed files: none, lzss or lz4.
+        #[arg(short, long, default_value_t = CompressionTypeT::Lzss)]
+        compression: CompressionTypeT,
+        /// How hard LZSS files are compressed: fast, balanced or exhaustive.
+        #[arg(short, long, default_value_t = CompressionLevel::default())]
+        level: CompressionLevel,
+    },
+    /// Read every file that matches the filter and report the ones that are damaged. Given a
+    /// directory, report its missing, extra and modified files instead.
+    Verify {
+        /// An extracted or modded directory to compare with the archives.
+        directory: Option<PathBuf>,
+        /// Compare the directory with this manifest from `checksums` instead of the archives.
+        #[arg(short, long, value_name = "FILE", requires = "directory")]
+        manifest: Option<PathBuf>,
+    },
+    /// Write the size, CRC32 and SHA-256 of every file that matches the filter, read from the
+    /// archives, to a manifest for `verify`.
+    Checksums {
+        /// The manifest to write.
+        output: PathBuf,
+    },
+    /// Write copies of the archives holding a file with its contents replaced. The install is
+    /// left as it is.
+    Replace {
+        /// Path inside the archives, e.g. `c:\ff8\data\eng\menu\mngrp.bin`.
+        path: String,
+        /// The new contents.
+        file: PathBuf,
+        /// The directory the rebuilt archives are written to.
+        output: PathBuf,
+        /// Compression of the new contents: none, lzss or lz4. Keeps the entry's when not given.
+        #[arg(short, long)]
+        compression: Option<CompressionTypeT>,
+    },
+    /// Write copies of an archive with a new file appended to it.
+    Add {
+        /// Path the file gets inside the archive.
+        path: String,
+        /// The contents of the new file.
+        file: PathBuf,
+        /// The directory the rebuilt archives are written to.
+        output: PathBuf,
+        /// The fs of the archive to add the file to, e.g. `data\lang-en\menu.fs`.
+        #[arg(short, long, value_name = "FS")]
+        archive: String,
+        /// Compression of the new file: none, lzss or lz4.
+        #[arg(short, long, default_value_t = CompressionTypeT::Lzss)]
+        compression: CompressionTypeT,
+    },
+    /// Write copies of the archives holding a file without it.
+    Remove {
+        /// Path inside the archives.
+        path: String,
+        /// The directory the rebuilt archives are written to.
+        output: PathBuf,
+    },
+    /// Scan the install again and rewrite the archive cache.
+    RebuildCache,
+}
+
+impl ConfigOverrides {
+    /// Applies the overrides to `config`, rejecting a filter or query that does not parse.
+    pub fn apply(&self, config: &mut Config) -> io::Result<()> {
+        let mut updated = config.clone();
+        if let Some(install_dir) = &self.install_dir {
+            updated.locations.chosen_directory = install_dir.clone();
+        }
+        if let Some(output_dir) = &self.output_dir {
+            updated.locations.extract_directory = output_dir.clone();
+        }
+        if let Some(filter) = &self.filter {
+            updated.extract_regex_filter = filter.clone();
+        }
+        if !self.include.is_empty() {
+            updated.include = self.include.clone();
+        }
+        if !self.exclude.is_empty() {
+            updated.exclude = self.exclude.clone();
+        }
+        file_filter(&updated)?;
+        *config = updated;
+        Ok(())
+    }
+}
+
+/// Runs one of the non-interactive commands with `config`, reporting errors on stderr.
+pub fn run(command: RunCommand, overrides: &ConfigOverrides, mut config: Config) -> ExitCode {
+    let result = overrides.apply(&mut config).and_then(|()| match command {
+        RunCommand::List { long, format } => list(&config, overrides, long, format),
+        RunCommand::Extract { dry_run: true, .. } => extract_plan(&config, overrides),
+        RunCommand::Extract { incremental, .. } => {
+            let zzz_files = load_install(&config, overrides)?;
+            extract_all_files(&zzz_files, &config, incremental).map(|()| ExitCode::SUCCESS)
+        }
+        RunCommand::Info => info(&config, overrides),
+        RunCommand::Cat { path } => cat(&config, overrides, &path),
+        RunCommand::Pack {
+            input_dir,
+            output,
+            zzz: true,
+            ..
+        } => pack_zzz(&input_dir, &output),
+        RunCommand::Pack {
+            input_dir,
+            output,
+            compression,
+            level,
+            ..
+        } => pack(&input_dir, &output, compression, level),
+        RunCommand::Verify {
+            directory: None, ..
+        } => verify(&config, overrides),
+        RunCommand::Verify {
+            directory: Some(directory),
+            manifest,
+        } => verify_directory(&config, overrides, &directory, manifest.as_deref()),
+        RunCommand::Checksums { output } => {
+            let manifest = install_checksums(&config, overrides)?;
+            manifest.save(&output)?;
+            println!(
+                "Wrote the checksums of {} files to {}",
+                manifest.files.len(),
+                output.display()
+            );
+            Ok(ExitCode::SUCCESS)
+        }
+        RunCommand::Replace {
+            path,
+            file,
+            output,
+            compression,
+        } => {
+            let edit = EntryEdit::Replace {
+                data: fs::read(&file)?,
+                compression_type: compression,
+            };
+            edit_entry(&config, overrides, &path, &edit, &output)
+        }
+        RunCommand::Add {
+            path,
+            file,
+            output,
+            archive,
+            compression,
+        } => {
+            let edit = EntryEdit::Add {
+                archive,
+                data: fs::read(&file)?,
+                compression_type: compression,
+            };
+            edit_entry(&config, overrides, &path, &edit, &output)
+        }
+        RunCommand::Remove { path, output } => {
+            edit_entry(&config, overrides, &path, &EntryEdit::Remove, &output)
+        }
+        RunCommand::RebuildCache => {
+            check_install_dir(&config)?;
+            rebuild_cache(&config).map(|_| ExitCode::SUCCESS)
+        }
+    });
+    result.unwrap_or_else(|err| {
+        eprintln!("Error: {}", err);
+        ExitCode::from(EXIT_FAILURE)
+    })
+}
+
+fn check_install_dir(config: &Config) -> io::Result<()> {
+    if !Path::new(&config.locations.chosen_directory).is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!(
+                "FF8 directory \"{}\" does not exist, pass --install-dir or choose one in the menu",
+                config.locations.chosen_directory
+            ),
+        ));
+    }
+    Ok(())
+}
+
+// The cache only describes the install chosen in config.toml, another one is scanned directly.
+fn load_install(config: &Config, overrides: &ConfigOverrides) -> io::Result<ZZZfiles> {
+    check_install_dir(config)?;
+    if overrides.install_dir.is_some() {
+        return load_archives(config);
+    }
+    load_or_rebuild_cache(config)
+}
+
+fn file_filter(config: &Config) -> io::Result<FileFilter> {
+    FileFilter::from_config(config).map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))
+}
+
+fn list(
+    config: &Config,
+    overrides: &ConfigOverrides,
+    long: bool,
+    format: Option<ListingFormat>,
+) -> io::Result<ExitCode> {
+    let zzz_files = load_install(config, overrides)?;
+    let filter = file_filter(config)?;
+    let mut stdout = io::stdout().lock();
+    if let Some(format) = format {
+        let entries = archive_files(&zzz_files)
+            .iter()
+            .zip(archive_listing(&zzz_files))
+            .filter(|(file, _)| filter.matches(file))
+            .map(|(_, entry)| entry)
+            .collect::<Vec<_>>();
+        write_listing(&entries, format, &mut stdout)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+    for file in archive_files(&zzz_files)
+        .iter()
+        .filter(|file| filter.matches(file))
+    {
+        if long {
+            writeln!(
+                stdout,
+                "{:>10} {:<4} {}",
+                file.uncompressed_size(),
+                file.compression_type(),
+                file.path
+            )?;
+        } else {
+            writeln!(stdout, "{}", file.path)?;
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn extract_plan(config: &Config, overrides: &ConfigOverrides) -> io::Result<ExitCode> {
+    let zzz_files = load_install(config, overrides)?;
+    let filter = file_filter(config)?;
+    let plan = ExtractionPlan::new(&zzz_files, &filter, &config.locations.extract_directory);
+    let mut stdout = io::stdout().lock();
+    for planned in &plan.files {
+        writeln!(
+            stdout,
+            "{:>10} {:<4} {} > {} -> {}",
+            planned.file.uncompressed_size(),
+            planned.file.compression_type(),
+            planned.file.archive_chain.join(" > "),
+            planned.file.path,
+            planned.destination.display()
+        )?;
+    }
+    let total_size = plan.total_size();
+    writeln!(
+        stdout,
+        "{} files, {} bytes ({:.1} MiB) would be written to {}",
+        plan.files.len(),
+        total_size,
+        total_size as f64 / (1024.0 * 1024.0),
+        config.locations.extract_directory
+    )?;
+    let conflicts = plan.conflicts();
+    if !conflicts.is_empty() {
+        writeln!(
+            stdout,
+            "{} destinations are written more than once, the last file wins:",
+            conflicts.len()
+        )?;
+        for conflict in conflicts {
+            writeln!(stdout, "  {}", conflict.destination.display())?;
+            for index in conflict.files {
+                let file = &plan.files[index].file;
+                writeln!(
+                    stdout,
+                    "    {} > {}",
+                    file.archive_chain.join(" > "),
+                    file.path
+                )?;
+            }
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn info(config: &Config, overrides: &ConfigOverrides) -> io::Result<ExitCode> {
+    let zzz_files = load_install(config, overrides)?;
+    println!("FF8 directory: {}", config.locations.chosen_directory);
+    match config
+        .locations
+        .releases
+        .get(&config.locations.chosen_directory)
+    {
+        Some(release) => println!("Release: {}", release),
+        None => println!(
+            "Release: {}",
+            detect_release(&config.locations.chosen_directory)?
+        ),
+    }
+    for zzz in zzz_files.into_iter().flatten() {
+        println!(
+            "{}: {} entries, {} fi/fl/fs archives",
+            zzz.file_path,
+            zzz.entries.len(),
+            zzz.fiflfs_files.as_ref().map_or(0, Vec::len)
+        );
+    }
+
+    let files = archive_files(&zzz_files);
+    let mut by_compression = BTreeMap::new();
+    for file in &files {
+        let (count, size) = by_compression
+            .entry(file.compression_type().to_string())
+            .or_insert((0usize, 0u64));
+        *count += 1;
+        *size += file.uncompressed_size();
+    }
+    println!(
+        "{} files, {} bytes uncompressed",
+        files.len(),
+        files
+            .iter()
+            .map(|file| file.uncompressed_size())
+            .sum::<u64>()
+    );
+    for (compression_type, (count, size)) in by_compression {
+        println!("  {:<4} {} files, {} bytes", compression_type, count, size);
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn cat(config: &Config, overrides: &ConfigOverrides, path: &str) -> io::Result<ExitCode> {
+    let zzz_files = load_install(config, overrides)?;
+    let Some(file) = zzz_files.find_file(path) else {
+        eprintln!("Error: \"{}\" was not found in any archive", path);
+        return Ok(ExitCode::from(EXIT_NOT_FOUND));
+    };
+    let mut stdout = io::stdout().lock();
+    stdout.write_all(&file.read()?)?;
+    stdout.flush()?;
+    Ok(ExitCode::SUCCESS)
+}
+
+fn pack(
+    input_dir: &Path,
+    output: &Path,
+    compression_type: CompressionTypeT,
+    level: CompressionLevel,
+) -> io::Result<ExitCode> {
+    let mut fiflfs_writer = FIFLFSWriter::new();
+    fiflfs_writer.set_lzss_level(level);
+    let mut files = Vec::new();
+    collect_files(input_dir, &mut files)?;
+    for file in files {
+        let relative_path = file.strip_prefix(input_dir).unwrap_or(&file);
+        fiflfs_writer.add_file(&relative_path.to_string_lossy(), &file, compression_type)?;
+    }
+
+    output.to_path_buf().create_directories()?;
+    let create = |extension: &str| -> io::Result<BufWriter<File>> {
+        Ok(BufWriter::new(File::create(with_extension(
+            output, extension,
+        ))?))
+    };
+    let fi_entries =
+        fiflfs_writer.write(&mut create("fi")?, &mut create("fl")?, &mut create("fs")?)?;
+    println!(
+        "Packed {} files into {}.fi/.fl/.fs",
+        fi_entries.len(),
+        output.display()
+    );
+    Ok(ExitCode::SUCCESS)
+}
+
+fn pack_zzz(input_dir: &Path, output: &Path) -> io::Result<ExitCode> {
+    let zzz_writer = ZZZWriter::from_directory(input_dir)?;
+    output.to_path_buf().create_directories()?;
+    let zzz_path = with_extension(output, "zzz");
+    zzz_writer.write_to_file(&zzz_path.to_string_lossy())?;
+    println!(
+        "Packed {} files into {}",
+        zzz_writer.len(),
+        zzz_path.display()
+    );
+    Ok(ExitCode::SUCCESS)
+}
+
+// Appended rather than set, so a name with a dot in it is kept whole.
+fn with_extension(path: &Path, extension: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(".");
+    path.push(extension);
+    PathBuf::from(path)
+}
+
+// Applies `edit` to the archive holding `fl_path` and writes the rebuilt archive to `output`.
+fn edit_entry(
+    config: &Config,
+    overrides: &ConfigOverrides,
+    fl_path: &str,
+    edit: &EntryEdit,
+    output: &Path,
+) -> io::Result<ExitCode> {
+    let zzz_files = load_install(config, overrides)?;
+    fs::create_dir_all(output)?;
+    if let Some(written) = edit_install(&zzz_files, fl_path, edit, output)? {
+        for path in written {
+            println!("Wrote {}", path.display());
+        }
+        return Ok(ExitCode::SUCCESS);
+    }
+    match edit {
+        EntryEdit::Add { archive, .. } => eprintln!("Error: no archive has the fs \"{}\"", archive),
+        _ => eprintln!("Error: \"{}\" was not found in any archive", fl_path),
+    }
+    Ok(ExitCode::from(EXIT_NOT_FOUND))
+}
+
+// Every file under `directory`, sorted so the packed order does not depend on the file system.
+fn collect_files(directory: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    let mut entries = fs::read_dir(directory)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn install_checksums(config: &Config, overrides: &ConfigOverrides) -> io::Result<ChecksumManifest> {
+    let zzz_files = load_install(config, overrides)?;
+    let filter = file_filter(config)?;
+    let plan = ExtractionPlan::new(&zzz_files, &filter, &config.locations.extract_directory);
+    Ok(ChecksumManifest::from_plan(&plan)?)
+}
+
+fn verify_directory(
+    config: &Config,
+    overrides: &ConfigOverrides,
+    directory: &Path,
+    manifest: Option<&Path>,
+) -> io::Result<ExitCode> {
+    let manifest = match manifest {
+        Some(manifest) => ChecksumManifest::load(manifest)?,
+        None => install_checksums(config, overrides)?,
+    };
+    let report = manifest.verify_directory(directory)?;
+    for (kind, paths) in [
+        ("missing", &report.missing),
+        ("extra", &report.extra),
+        ("modified", &report.modified),
+    ] {
+        for path in paths {
+            println!("{}: {}", kind, path);
+        }
+    }
+    println!(
+        "Compared {} files, {} missing, {} extra, {} modified",
+        manifest.files.len(),
+        report.missing.len(),
+        report.extra.len(),
+        report.modified.len()
+    );
+    Ok(if report.is_clean() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::from(EXIT_DAMAGED)
+    })
+}
+
+fn verify(config: &Config, overrides: &ConfigOverrides) -> io::Result<ExitCode> {
+    let zzz_files = load_install(config, overrides)?;
+    let filter = file_filter(config)?;
+    let mut checked = 0usize;
+    let mut damaged = 0usize;
+    let mut report = |path: &str, result: ArchiveResult<u64>| {
+        checked += 1;
+        if let Err(err) = result {
+            damaged += 1;
+            eprintln!("{}: {}", path, err);
+        }
+    };
+
+    let mut copier = FileCopier::new();
+    for file in archive_files(&zzz_files)
+        .iter()
+        .filter(|file| filter.matches(file))
+    {
+        report(file.path, copier.copy_file(file, &mut io::sink()));
+    }
+    // archive_files skips the archives it can not list, they are damaged too.
+    for zzz in zzz_files.into_iter().flatten() {
+        for archive in zzz.fiflfs_files.iter().flatten() {
+            report_unloaded_archives(archive, &mut report);
+        }
+    }
+
+    println!("Checked {} files, {} damaged", checked, damaged);
+    Ok(if damaged == 0 {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::from(EXIT_DAMAGED)
+    })
+}
+
+fn report_unloaded_archives<F>(archive: &FIFLFSZZZ, report: &mut F)
+where
+    F: FnMut(&str, ArchiveResult<u64>),
+{
+    if archive.fi_file.is_none() || archive.fl_file.is_none() {
+        report(
+            &archive.fs.string_data,
+            Err(ArchiveError::from(io::Error::new(
+                io::ErrorKind::NotFound,
+                "fi and fl are not loaded",
+            ))),
+        );
+    }
+    for child in archive.field_archives.iter().flatten() {
+        report_unloaded_archives(child, report);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn test_cli_parse() {
+        Cli::command().debug_assert();
+
+        let cli =
+            Cli::try_parse_from(["oviiirs", "pack", "in", "out/field", "-l", "fast"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Command::Run(RunCommand::Pack {
+                compression: CompressionTypeT::Lzss,
+                level: CompressionLevel::Fast,
+                ..
+            }))
+        ));
+
+        let cli = Cli::try_parse_from(["oviiirs", "pack", "in", "out/main", "--zzz"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Command::Run(RunCommand::Pack { zzz: true, .. }))
+        ));
+        assert!(
+            Cli::try_parse_from(["oviiirs", "pack", "in", "out", "--zzz", "-c", "lz4"]).is_err()
+        );
+        let cli = Cli::try_parse_from(["oviiirs", "menu"]).unwrap();
+        assert!(matches!(cli.command, Some(Command::Menu)));
+
+        // The overrides are accepted after the command too.
+        let cli =
+            Cli::try_parse_from(["oviiirs", "list", "-d", "ff8", "--filter", "\\.msd$"]).unwrap();
+        let mut config = Config::default();
+        cli.overrides.apply(&mut config).unwrap();
+        assert_eq!(config.locations.chosen_directory, "ff8");
+        assert_eq!(config.extract_regex_filter, "\\.msd$");
+
+        let overrides = ConfigOverrides {
+            filter: Some("(".to_string()),
+            ..Default::default()
+        };
+        assert!(overrides.apply(&mut config).is_err());
+        let cli = Cli::try_parse_from([
+            "oviiirs",
+            "extract",
+            "-i",
+            "lang:fr *.tex",
+            "-i",
+            "*.tim",
+            "-x",
+            "size>1MB",
+        ])
+        .unwrap();
+        cli.overrides.apply(&mut config).unwrap();
+        assert_eq!(config.include, ["lang:fr *.tex", "*.tim"]);
+        assert_eq!(config.exclude, ["size>1MB"]);
+        assert!(matches!(
+            cli.command,
+            Some(Command::Run(RunCommand::Extract {
+                dry_run: false,
+                incremental: false
+            }))
+        ));
+        let cli = Cli::try_parse_from(["oviiirs", "extract", "--dry-run"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Command::Run(RunCommand::Extract { dry_run: true, .. }))
+        ));
+        assert!(Cli::try_parse_from(["oviiirs", "extract", "-n", "-u"]).is_err());
+        assert!(Cli::try_parse_from(["oviiirs", "verify", "-m", "sums.toml"]).is_err());
+        let cli = Cli::try_parse_from(["oviiirs", "verify", "out", "-m", "sums.toml"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Command::Run(RunCommand::Verify {
+                directory: Some(_),
+                manifest: Some(_)
+            }))
+        ));
+        let overrides = ConfigOverrides {
+            exclude: vec!["size>".to_string()],
+            ..Default::default()
+        };
+        assert!(overrides.apply(&mut config).is_err());
+        assert!(Cli::try_parse_from(["oviiirs", "pack", "in", "out", "-c", "zip"]).is_err());
+
+        let cli = Cli::try_parse_from(["oviiirs", "list", "--format", "ndjson"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Command::Run(RunCommand::List {
+                format: Some(ListingFormat::Ndjson),
+                ..
+            }))
+        ));
+        assert!(Cli::try_parse_from(["oviiirs", "list", "--long", "--format", "csv"]).is_err());
+
+        let cli = Cli::try_parse_from(["oviiirs", "replace", "menu\\mngrp.bin", "new.bin", "out"])
+            .unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Command::Run(RunCommand::Replace {
+                compression: None,
+                ..
+            }))
+        ));
+        assert!(
+            Cli::try_parse_from(["oviiirs", "add", "menu\\new.bin", "new.bin", "out"]).is_err()
+        );
+        let cli = Cli::try_parse_from([
+            "oviiirs",
+            "add",
+            "menu\\new.bin",
+            "new.bin",
+            "out",
+            "--archive",
+            "data\\lang-en\\menu.fs",
+        ])
+        .unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Command::Run(RunCommand::Add {
+                compression: CompressionTypeT::Lzss,
+                ..
+            }))
+        ));
+        let cli = Cli::try_parse_from(["oviiirs", "remove", "menu\\mngrp.bin", "out"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Command::Run(RunCommand::Remove { .. }))
+        ));
+    }
+}
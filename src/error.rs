@@ -0,0 +1,272 @@
+use crate::lzss::CompressionError;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::string::FromUtf8Error;
+
+pub type ArchiveResult<T> = Result<T, ArchiveError>;
+
+/// Where in an archive an error happened. Not every failure knows all three, e.g. a reader has
+/// no path until the caller that opened it fills one in.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ErrorLocation {
+    pub path: Option<String>,
+    pub entry_index: Option<usize>,
+    pub offset: Option<u64>,
+}
+
+impl fmt::Display for ErrorLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(path) = &self.path {
+            write!(f, " in {}", path)?;
+        }
+        if let Some(entry_index) = self.entry_index {
+            write!(f, " at entry {}", entry_index)?;
+        }
+        if let Some(offset) = self.offset {
+            write!(f, " at offset {}", offset)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum ArchiveError {
+    /// The file could not be opened or read, e.g. it does not exist.
+    Io {
+        location: ErrorLocation,
+        source: io::Error,
+    },
+    /// A header, entry table or compression header ends early.
+    TruncatedHeader {
+        location: ErrorLocation,
+        section: &'static str,
+    },
+    /// An entry points past the end of the data that should contain it.
+    EntryOutOfBounds {
+        location: ErrorLocation,
+        size: u64,
+        available: u64,
+    },
+    /// An fi record uses a compression type that does not exist.
+    BadCompressionCode { location: ErrorLocation, code: u32 },
+    /// The fi and fl of an archive list a different number of files.
+    CountMismatch {
+        location: ErrorLocation,
+        fi_count: usize,
+        fl_count: usize,
+    },
+    /// A path stored in a zzz or fl is not valid UTF-8.
+    InvalidPath {
+        location: ErrorLocation,
+        source: FromUtf8Error,
+    },
+    /// The data of an entry could not be decompressed.
+    Decompression {
+        location: ErrorLocation,
+        message: String,
+    },
+}
+
+impl ArchiveError {
+    pub fn location(&self) -> &ErrorLocation {
+        match self {
+            ArchiveError::Io { location, .. }
+            | ArchiveError::TruncatedHeader { location, .. }
+            | ArchiveError::EntryOutOfBounds { location, .. }
+            | ArchiveError::BadCompressionCode { location, .. }
+            | ArchiveError::CountMismatch { location, .. }
+            | ArchiveError::InvalidPath { location, .. }
+            | ArchiveError::Decompression { location, .. } => location,
+        }
+    }
+
+    fn location_mut(&mut self) -> &mut ErrorLocation {
+        match self {
+            ArchiveError::Io { location, .. }
+            | ArchiveError::TruncatedHeader { location, .. }
+            | ArchiveError::EntryOutOfBounds { location, .. }
+            | ArchiveError::BadCompressionCode { location, .. }
+            | ArchiveError::CountMismatch { location, .. }
+            | ArchiveError::InvalidPath { location, .. }
+            | ArchiveError::Decompression { location, .. } => location,
+        }
+    }
+
+    /// True when the archive data itself is damaged, as opposed to the file failing to open or
+    /// read.
+    pub fn is_corrupt(&self) -> bool {
+        !matches!(self, ArchiveError::Io { .. })
+    }
+
+    pub fn is_not_found(&self) -> bool {
+        matches!(self, ArchiveError::Io { source, .. } if source.kind() == io::ErrorKind::NotFound)
+    }
+
+    // A read that ran out of data means `section` was cut short, anything else is passed on.
+    pub(crate) fn from_read(err: io::Error, section: &'static str) -> Self {
+        match err.kind() {
+            io::ErrorKind::UnexpectedEof => ArchiveError::TruncatedHeader {
+                location: ErrorLocation::default(),
+                section,
+            },
+            _ => err.into(),
+        }
+    }
+
+    /// Fills in the path unless a more specific one is already known.
+    pub fn with_path(mut self, path: &str) -> Self {
+        self.location_mut()
+            .path
+            .get_or_insert_with(|| path.to_string());
+        self
+    }
+
+    pub fn with_entry_index(mut self, entry_index: usize) -> Self {
+        self.location_mut().entry_index.get_or_insert(entry_index);
+        self
+    }
+
+    pub fn with_offset(mut self, offset: u64) -> Self {
+        self.location_mut().offset.get_or_insert(offset);
+        self
+    }
+}
+
+impl fmt::Display for ArchiveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArchiveError::Io { source, .. } => write!(f, "I/O error: {}", source)?,
+            ArchiveError::TruncatedHeader { section, .. } => write!(f, "Truncated {}", section)?,
+            ArchiveError::EntryOutOfBounds {
+                size, available, ..
+            } => write!(
+                f,
+                "Entry of {} bytes does not fit in the {} bytes available",
+                size, available
+            )?,
+            ArchiveError::BadCompressionCode { code, .. } => {
+                write!(f, "Unknown compression type {}", code)?
+            }
+            ArchiveError::CountMismatch {
+                fi_count, fl_count, ..
+            } => write!(f, "fi lists {} entries but fl lists {}", fi_count, fl_count)?,
+            ArchiveError::InvalidPath { source, .. } => {
+                write!(f, "Path is not valid UTF-8: {}", source)?
+            }
+            ArchiveError::Decompression { message, .. } => {
+                write!(f, "Decompression failed: {}", message)?
+            }
+        }
+        write!(f, "{}", self.location())
+    }
+}
+
+impl Error for ArchiveError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ArchiveError::Io { source, .. } => Some(source),
+            ArchiveError::InvalidPath { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ArchiveError {
+    fn from(err: io::Error) -> Self {
+        // An ArchiveError that went through an io::Result, e.g. a VirtualFs call, comes back out
+        // unchanged.
+        if err
+            .get_ref()
+            .is_some_and(|inner| inner.is::<ArchiveError>())
+        {
+            return *err.into_inner().unwrap().downcast().unwrap();
+        }
+        if err
+            .get_ref()
+            .is_some_and(|inner| inner.is::<CompressionError>())
+        {
+            return (*err
+                .into_inner()
+                .unwrap()
+                .downcast::<CompressionError>()
+                .unwrap())
+            .into();
+        }
+        ArchiveError::Io {
+            location: ErrorLocation::default(),
+            source: err,
+        }
+    }
+}
+
+impl From<CompressionError> for ArchiveError {
+    fn from(err: CompressionError) -> Self {
+        ArchiveError::Decompression {
+            location: ErrorLocation::default(),
+            message: err.to_string(),
+        }
+    }
+}
+
+impl From<ArchiveError> for io::Error {
+    fn from(err: ArchiveError) -> Self {
+        let kind = match &err {
+            ArchiveError::Io { source, .. } => source.kind(),
+            ArchiveError::TruncatedHeader { .. } => io::ErrorKind::UnexpectedEof,
+            _ => io::ErrorKind::InvalidData,
+        };
+        io::Error::new(kind, err)
+    }
+}
+
+// Adds location details to any result whose error converts into an ArchiveError.
+pub(crate) trait ResultExt<T> {
+    fn with_path(self, path: &str) -> ArchiveResult<T>;
+    fn with_entry_index(self, entry_index: usize) -> ArchiveResult<T>;
+    fn with_offset(self, offset: u64) -> ArchiveResult<T>;
+}
+
+impl<T, E: Into<ArchiveError>> ResultExt<T> for Result<T, E> {
+    fn with_path(self, path: &str) -> ArchiveResult<T> {
+        self.map_err(|err| err.into().with_path(path))
+    }
+
+    fn with_entry_index(self, entry_index: usize) -> ArchiveResult<T> {
+        self.map_err(|err| err.into().with_entry_index(entry_index))
+    }
+
+    fn with_offset(self, offset: u64) -> ArchiveResult<T> {
+        self.map_err(|err| err.into().with_offset(offset))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_archive_error_io_round_trip() {
+        let err = ArchiveError::BadCompressionCode {
+            location: ErrorLocation::default(),
+            code: 7,
+        }
+        .with_entry_index(3)
+        .with_path("field.fi")
+        .with_path("ignored.fi");
+        assert_eq!(
+            err.to_string(),
+            "Unknown compression type 7 in field.fi at entry 3"
+        );
+
+        let io_err = io::Error::from(err);
+        assert_eq!(io_err.kind(), io::ErrorKind::InvalidData);
+        let err = ArchiveError::from(io_err);
+        assert!(err.is_corrupt());
+        assert_eq!(err.location().entry_index, Some(3));
+
+        let err = ArchiveError::from(io::Error::from(io::ErrorKind::NotFound));
+        assert!(err.is_not_found());
+        assert!(!err.is_corrupt());
+    }
+}
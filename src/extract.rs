@@ -0,0 +1,287 @@
+use crate::archive_edit::raw_entry_bytes;
+use crate::archive_reader::ArchiveReader;
+use crate::error::{ArchiveResult, ResultExt};
+use crate::oviiirs_archive::{
+    read_entry_bytes_from_memory, CompressionTypeT, CreateDirectories, ZZZEntry, FI, FIFLFSZZZ,
+};
+use crate::vfs::{ArchiveFile, ArchiveFileSource};
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::io::Write;
+use std::path::PathBuf;
+
+/// How the entries of a fs are reached while extracting.
+#[derive(Debug)]
+pub enum FsSource {
+    /// The fs is stored uncompressed, so each entry is read in place from the file.
+    File { reader: ArchiveReader, offset: u64 },
+    /// The fs is compressed inside its parent and has to be decompressed first. It is a single
+    /// entry of the parent, so this is never more than one entry's worth of memory.
+    Memory(Vec<u8>),
+}
+
+impl FsSource {
+    pub fn open(archive: &FIFLFSZZZ) -> ArchiveResult<Self> {
+        let reader = ArchiveReader::open(archive.fs_file_path())?;
+        Ok(match archive.fs.compression_type {
+            CompressionTypeT::None => FsSource::File {
+                reader,
+                offset: archive.fs.file_offset,
+            },
+            _ => FsSource::Memory(reader.read_entry_bytes(&archive.fs)?),
+        })
+    }
+
+    /// Decompresses the entry described by `fi` into `writer`. Uncompressed and LZSS entries are
+    /// streamed through a small buffer, LZ4 ones are held in memory one at a time.
+    pub fn copy_entry<W: Write>(&self, fi: &FI, writer: &mut W) -> ArchiveResult<u64> {
+        match self {
+            FsSource::Memory(fs_bytes) => {
+                let bytes = read_entry_bytes_from_memory(fs_bytes, fi)?;
+                writer.write_all(&bytes)?;
+                Ok(bytes.len() as u64)
+            }
+            FsSource::File { reader, offset } => {
+                let offset = offset + fi.offset as u64;
+                match fi.compression_type {
+                    CompressionTypeT::None => {
+                        reader.copy_range(offset, fi.uncompressed_size as u64, writer)
+                    }
+                    CompressionTypeT::Lzss => {
+                        let mut lzss_reader =
+                            reader.lzss_reader(offset, fi.uncompressed_size as u64)?;
+                        Ok(io::copy(&mut lzss_reader, writer).with_offset(offset)?)
+                    }
+                    CompressionTypeT::Lz4 => {
+                        let bytes = reader.read_entry_bytes_at(
+                            offset,
+                            fi.uncompressed_size,
+                            fi.compression_type,
+                        )?;
+                        writer.write_all(&bytes)?;
+                        Ok(bytes.len() as u64)
+                    }
+                }
+            }
+        }
+    }
+
+    /// Copies the entry described by `fi` into `writer` as it is stored in the fs, compressed
+    /// and with the size header of compressed entries.
+    pub fn copy_stored_entry<W: Write>(&self, fi: &FI, writer: &mut W) -> ArchiveResult<u64> {
+        match self {
+            FsSource::Memory(fs_bytes) => {
+                let bytes = raw_entry_bytes(fs_bytes, fi)?;
+                writer.write_all(bytes)?;
+                Ok(bytes.len() as u64)
+            }
+            FsSource::File { reader, offset } => {
+                let offset = offset + fi.offset as u64;
+                let size = match fi.compression_type {
+                    _ if fi.uncompressed_size == 0 => 0,
+                    CompressionTypeT::None => fi.uncompressed_size as u64,
+                    CompressionTypeT::Lzss | CompressionTypeT::Lz4 => {
+                        let mut size = [0u8; 4];
+                        reader.read_exact_at(&mut size, offset)?;
+                        4 + u32::from_le_bytes(size) as u64
+                    }
+                };
+                reader.copy_range(offset, size, writer)
+            }
+        }
+    }
+
+    /// Writes the entry to `output_path`, creating its parent directories.
+    pub fn extract_entry(&self, fi: &FI, output_path: &PathBuf) -> ArchiveResult<u64> {
+        write_output(output_path, |writer| self.copy_entry(fi, writer))
+    }
+}
+
+/// Copies an entry of a `.zzz` into `writer` without loading it into memory.
+pub fn copy_zzz_entry<W: Write>(
+    reader: &ArchiveReader,
+    entry: &ZZZEntry,
+    writer: &mut W,
+) -> ArchiveResult<u64> {
+    reader.copy_range(entry.file_offset, entry.file_size as u64, writer)
+}
+
+/// Writes an entry of a `.zzz` to `output_path`, creating its parent directories.
+pub fn extract_zzz_entry(
+    reader: &ArchiveReader,
+    entry: &ZZZEntry,
+    output_path: &PathBuf,
+) -> ArchiveResult<u64> {
+    write_output(output_path, |writer| copy_zzz_entry(reader, entry, writer))
+}
+
+/// Copies [`ArchiveFile`]s out of the install. The last `.zzz` and fs used stay open, so going
+/// through the files in the order of `archive_files` opens each archive once.
+#[derive(Debug, Default)]
+pub struct FileCopier<'a> {
+    zzz: Option<(&'a str, ArchiveReader)>,
+    fs: Option<(&'a FIFLFSZZZ, FsSource)>,
+}
+
+impl<'a> FileCopier<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn copy_file<W: Write>(
+        &mut self,
+        file: &ArchiveFile<'a>,
+        writer: &mut W,
+    ) -> ArchiveResult<u64> {
+        match file.source {
+            ArchiveFileSource::ZZZ { zzz, entry } => {
+                copy_zzz_entry(self.zzz_reader(&zzz.file_path)?, entry, writer)
+            }
+            ArchiveFileSource::FIFLFS { archive, fi, .. } => {
+                self.fs_source(archive)?.copy_entry(fi, writer)
+            }
+        }
+    }
+
+    /// Reads and decompresses the whole file.
+    pub fn read_file(&mut self, file: &ArchiveFile<'a>) -> ArchiveResult<Vec<u8>> {
+        let mut bytes = Vec::with_capacity(file.uncompressed_size() as usize);
+        self.copy_file(file, &mut bytes)?;
+        Ok(bytes)
+    }
+
+    /// Copies the file as it is stored in its archive, without decompressing it.
+    pub fn copy_stored_file<W: Write>(
+        &mut self,
+        file: &ArchiveFile<'a>,
+        writer: &mut W,
+    ) -> ArchiveResult<u64> {
+        match file.source {
+            // The entries of a .zzz are never compressed.
+            ArchiveFileSource::ZZZ { .. } => self.copy_file(file, writer),
+            ArchiveFileSource::FIFLFS { archive, fi, .. } => {
+                self.fs_source(archive)?.copy_stored_entry(fi, writer)
+            }
+        }
+    }
+
+    fn zzz_reader(&mut self, file_path: &'a str) -> ArchiveResult<&ArchiveReader> {
+        let reader = match self.zzz.take() {
+            Some((path, reader)) if path == file_path => reader,
+            _ => ArchiveReader::open(file_path)?,
+        };
+        Ok(&self.zzz.insert((file_path, reader)).1)
+    }
+
+    fn fs_source(&mut self, archive: &'a FIFLFSZZZ) -> ArchiveResult<&FsSource> {
+        let fs_source = match self.fs.take() {
+            Some((cached, fs_source)) if std::ptr::eq(cached, archive) => fs_source,
+            _ => FsSource::open(archive)?,
+        };
+        Ok(&self.fs.insert((archive, fs_source)).1)
+    }
+
+    /// Writes the file to `output_path`, creating its parent directories.
+    pub fn extract_file(
+        &mut self,
+        file: &ArchiveFile<'a>,
+        output_path: &PathBuf,
+    ) -> ArchiveResult<u64> {
+        write_output(output_path, |writer| self.copy_file(file, writer))
+    }
+}
+
+pub(crate) fn write_output<F>(output_path: &PathBuf, copy: F) -> ArchiveResult<u64>
+where
+    F: FnOnce(&mut BufWriter<File>) -> ArchiveResult<u64>,
+{
+    let display_path = output_path.display().to_string();
+    output_path.create_directories().with_path(&display_path)?;
+    let mut writer = BufWriter::new(File::create(output_path).with_path(&display_path)?);
+    let written = copy(&mut writer).with_path(&display_path)?;
+    writer.flush().with_path(&display_path)?;
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fiflfs_writer::FIFLFSWriter;
+    use crate::oviiirs_archive::ReadEntries;
+    use crate::test_util::TempDir;
+    use std::fs;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_fs_source_copy_entry() {
+        let directory = TempDir::new("extract");
+        let container_path = directory.join("container.bin");
+
+        let mut fiflfs_writer = FIFLFSWriter::new();
+        let contents = [
+            (b"plain bytes".repeat(3), CompressionTypeT::None),
+            (b"lzss bytes".repeat(30), CompressionTypeT::Lzss),
+            (b"lz4 bytes".repeat(30), CompressionTypeT::Lz4),
+        ];
+        for (index, (data, compression_type)) in contents.iter().enumerate() {
+            fiflfs_writer
+                .add_bytes(&format!("ff8\\{}", index), data.clone(), *compression_type)
+                .unwrap();
+        }
+        let streams = fiflfs_writer.to_streams().unwrap();
+        let fi_entries = FI::read_entries(&mut Cursor::new(&streams.fi)).unwrap();
+
+        // The fs sits after some unrelated bytes, like it would inside a zzz.
+        let mut container = vec![0xAAu8; 37];
+        container.extend_from_slice(&streams.fs);
+        fs::write(&container_path, &container).unwrap();
+        let fs_lzss =
+            crate::fiflfs_writer::frame_entry(&streams.fs, CompressionTypeT::Lzss).unwrap();
+        let compressed_path = directory.join("compressed.bin");
+        fs::write(&compressed_path, &fs_lzss).unwrap();
+
+        for (file_path, compression_type) in [
+            (&container_path, CompressionTypeT::None),
+            (&compressed_path, CompressionTypeT::Lzss),
+        ] {
+            let archive = FIFLFSZZZ {
+                file_path: file_path.to_str().unwrap().to_string(),
+                fs: ZZZEntry {
+                    file_offset: if compression_type == CompressionTypeT::None {
+                        37
+                    } else {
+                        0
+                    },
+                    file_size: streams.fs.len() as u32,
+                    compression_type,
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            let fs_source = FsSource::open(&archive).unwrap();
+            assert_eq!(
+                matches!(fs_source, FsSource::File { .. }),
+                compression_type == CompressionTypeT::None
+            );
+            for (fi, (data, _)) in fi_entries.iter().zip(&contents) {
+                let mut output = Vec::new();
+                assert_eq!(
+                    fs_source.copy_entry(fi, &mut output).unwrap(),
+                    data.len() as u64
+                );
+                assert_eq!(&output, data);
+            }
+        }
+
+        let output_path = directory.join("out").join("entry.bin");
+        let entry = ZZZEntry {
+            file_offset: 37,
+            file_size: 4,
+            ..Default::default()
+        };
+        let reader = ArchiveReader::open(container_path.to_str().unwrap()).unwrap();
+        extract_zzz_entry(&reader, &entry, &output_path).unwrap();
+        assert_eq!(fs::read(&output_path).unwrap(), &streams.fs[..4]);
+    }
+}
@@ -0,0 +1,297 @@
+use crate::lzss::{compress_with_level, CompressionLevel};
+use crate::oviiirs_archive::{
+    lz4_compress_framed, CompressionTypeT, GenerateWindowsPath, WriteEntry, FI, FL,
+};
+use crate::zzz_writer::ZZZWriter;
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone)]
+enum FIFLFSSource {
+    Bytes(Vec<u8>),
+    File(PathBuf),
+}
+
+#[derive(Debug, Clone)]
+struct FIFLFSPendingEntry {
+    path: String,
+    source: FIFLFSSource,
+    compression_type: CompressionTypeT,
+}
+
+/// The three streams of a packed archive.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct FIFLFSStreams {
+    pub fi: Vec<u8>,
+    pub fl: Vec<u8>,
+    pub fs: Vec<u8>,
+}
+
+/// Packs files into a consistent fi/fl/fs set. Every file is compressed on its own with the
+/// chosen `CompressionTypeT` and framed the way the readers in this crate expect.
+#[derive(Debug, Default, Clone)]
+pub struct FIFLFSWriter {
+    entries: Vec<FIFLFSPendingEntry>,
+    known_paths: HashSet<String>,
+    lzss_level: CompressionLevel,
+}
+
+impl FIFLFSWriter {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// How hard LZSS entries are compressed. The default matches the game's own files, a faster
+    /// level makes repacking while modding much quicker at the cost of a larger fs.
+    pub fn set_lzss_level(&mut self, level: CompressionLevel) {
+        self.lzss_level = level;
+    }
+
+    /// Adds an in-memory file. `path` is stored in the fl file with the usual `c:\` prefix,
+    /// e.g. `c:\ff8\data\eng\field\model\main_chr.fs`.
+    pub fn add_bytes(
+        &mut self,
+        path: &str,
+        data: Vec<u8>,
+        compression_type: CompressionTypeT,
+    ) -> io::Result<()> {
+        self.push(path, FIFLFSSource::Bytes(data), compression_type)
+    }
+
+    /// Adds a file from disk, it is read when the archive is written.
+    pub fn add_file<P: Into<PathBuf>>(
+        &mut self,
+        path: &str,
+        source: P,
+        compression_type: CompressionTypeT,
+    ) -> io::Result<()> {
+        self.push(path, FIFLFSSource::File(source.into()), compression_type)
+    }
+
+    fn push(
+        &mut self,
+        path: &str,
+        source: FIFLFSSource,
+        compression_type: CompressionTypeT,
+    ) -> io::Result<()> {
+        if path.trim().is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Archive path can not be empty",
+            ));
+        }
+        let path = path.generate_windows_path_with_prefix();
+        if !self.known_paths.insert(path.to_lowercase()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Duplicate archive path: {}", path),
+            ));
+        }
+        self.entries.push(FIFLFSPendingEntry {
+            path,
+            source,
+            compression_type,
+        });
+        Ok(())
+    }
+
+    /// Writes the three streams, returning the FI entries that were written.
+    pub fn write<WI: Write, WL: Write, WS: Write>(
+        &self,
+        fi_writer: &mut WI,
+        fl_writer: &mut WL,
+        fs_writer: &mut WS,
+    ) -> io::Result<Vec<FI>> {
+        let mut fs_offset = 0u64;
+        let mut fi_entries = Vec::with_capacity(self.entries.len());
+
+        for entry in &self.entries {
+            let data = match &entry.source {
+                FIFLFSSource::Bytes(bytes) => std::borrow::Cow::Borrowed(bytes.as_slice()),
+                FIFLFSSource::File(path) => std::borrow::Cow::Owned(fs::read(path)?),
+            };
+            let uncompressed_size = u32::try_from(data.len()).map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{} is too large for a FI entry", entry.path),
+                )
+            })?;
+            let offset = u32::try_from(fs_offset).map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{} starts past the 4GiB limit of the fs file", entry.path),
+                )
+            })?;
+
+            let framed = frame_entry_with_level(&data, entry.compression_type, self.lzss_level)?;
+            fs_writer.write_all(&framed)?;
+            fs_offset += framed.len() as u64;
+
+            let fi = FI {
+                uncompressed_size,
+                offset,
+                compression_type: entry.compression_type,
+            };
+            fi.write_entry(fi_writer)?;
+            FL::from(entry.path.clone()).write_entry(fl_writer)?;
+            fi_entries.push(fi);
+        }
+
+        fi_writer.flush()?;
+        fl_writer.flush()?;
+        fs_writer.flush()?;
+        Ok(fi_entries)
+    }
+
+    /// Packs everything into memory.
+    pub fn to_streams(&self) -> io::Result<FIFLFSStreams> {
+        let mut streams = FIFLFSStreams::default();
+        self.write(&mut streams.fi, &mut streams.fl, &mut streams.fs)?;
+        Ok(streams)
+    }
+
+    /// Packs the archive and adds it to a ZZZ as `base_path.fi`, `base_path.fl` and
+    /// `base_path.fs`, e.g. `data\lang-en\field`.
+    pub fn add_to_zzz(&self, zzz_writer: &mut ZZZWriter, base_path: &str) -> io::Result<()> {
+        let streams = self.to_streams()?;
+        zzz_writer.add_bytes(&format!("{}.fi", base_path), streams.fi)?;
+        zzz_writer.add_bytes(&format!("{}.fl", base_path), streams.fl)?;
+        zzz_writer.add_bytes(&format!("{}.fs", base_path), streams.fs)?;
+        Ok(())
+    }
+}
+
+/// Compresses `data` and adds the header that precedes it in the fs file.
+pub fn frame_entry(data: &[u8], compression_type: CompressionTypeT) -> io::Result<Vec<u8>> {
+    frame_entry_with_level(data, compression_type, CompressionLevel::default())
+}
+
+/// Same as [`frame_entry`], compressing LZSS entries at `lzss_level`.
+pub fn frame_entry_with_level(
+    data: &[u8],
+    compression_type: CompressionTypeT,
+    lzss_level: CompressionLevel,
+) -> io::Result<Vec<u8>> {
+    match compression_type {
+        CompressionTypeT::None => Ok(data.to_vec()),
+        CompressionTypeT::Lzss => {
+            let compressed = compress_with_level(data, lzss_level);
+            let mut framed = Vec::with_capacity(compressed.len() + 4);
+            framed.extend_from_slice(&(compressed.len() as u32).to_le_bytes());
+            framed.extend_from_slice(&compressed);
+            Ok(framed)
+        }
+        CompressionTypeT::Lz4 => Ok(lz4_compress_framed(data)?),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::oviiirs_archive::{
+        lz4_decompress_framed, read_bytes_from_memory,
+        read_compressed_bytes_from_memory_at_offset_lzss, read_entry_bytes_from_memory,
+        BufReadEntries, ReadEntries,
+    };
+    use std::io::BufReader;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_fiflfs_writer_round_trip() {
+        let text = b"Squall Rinoa Zell Selphie Quistis Irvine Squall Rinoa ".repeat(40);
+        let mut fiflfs_writer = FIFLFSWriter::new();
+        fiflfs_writer
+            .add_bytes(
+                "ff8\\data\\eng\\a.txt",
+                text.clone(),
+                CompressionTypeT::None,
+            )
+            .unwrap();
+        fiflfs_writer
+            .add_bytes(
+                "c:\\ff8\\data\\eng\\b.txt",
+                text.clone(),
+                CompressionTypeT::Lzss,
+            )
+            .unwrap();
+        fiflfs_writer
+            .add_bytes(
+                "c:\\ff8\\data\\eng\\c.txt",
+                text.clone(),
+                CompressionTypeT::Lz4,
+            )
+            .unwrap();
+        fiflfs_writer
+            .add_bytes("c:\\ff8\\data\\eng\\empty", vec![], CompressionTypeT::None)
+            .unwrap();
+
+        let streams = fiflfs_writer.to_streams().unwrap();
+
+        let fi_entries = FI::read_entries(&mut Cursor::new(&streams.fi)).unwrap();
+        let fl_entries = FL::read_entries(&mut BufReader::new(Cursor::new(&streams.fl))).unwrap();
+        assert_eq!(fi_entries.len(), 4);
+        assert_eq!(fl_entries.len(), 4);
+        assert_eq!(fl_entries[0].as_str(), "c:\\ff8\\data\\eng\\a.txt");
+        assert_eq!(fi_entries[0].offset, 0);
+        assert_eq!(fi_entries[3].uncompressed_size, 0);
+
+        let none = &fi_entries[0];
+        assert_eq!(
+            read_bytes_from_memory(
+                &streams.fs,
+                none.offset as usize,
+                none.uncompressed_size as usize
+            )
+            .unwrap(),
+            text
+        );
+
+        let lzss = &fi_entries[1];
+        assert_eq!(lzss.compression_type, CompressionTypeT::Lzss);
+        assert_eq!(
+            crate::lzss::decompress(
+                &read_compressed_bytes_from_memory_at_offset_lzss(
+                    &streams.fs,
+                    lzss.offset as usize
+                )
+                .unwrap(),
+                lzss.uncompressed_size as usize
+            ),
+            text
+        );
+
+        let lz4 = &fi_entries[2];
+        let lz4_start = lz4.offset as usize;
+        let lz4_size =
+            u32::from_le_bytes(streams.fs[lz4_start..lz4_start + 4].try_into().unwrap()) as usize;
+        assert_eq!(&streams.fs[lz4_start + 4..lz4_start + 8], b"4ZL_");
+        assert_eq!(lz4_start + 4 + lz4_size, fi_entries[3].offset as usize);
+        assert_eq!(
+            lz4_decompress_framed(
+                &streams.fs[lz4_start..lz4_start + 4 + lz4_size],
+                lz4.uncompressed_size
+            )
+            .unwrap(),
+            text
+        );
+
+        fiflfs_writer.set_lzss_level(CompressionLevel::Fast);
+        let fast_streams = fiflfs_writer.to_streams().unwrap();
+        let fast_fi_entries = FI::read_entries(&mut Cursor::new(&fast_streams.fi)).unwrap();
+        assert_eq!(
+            read_entry_bytes_from_memory(&fast_streams.fs, &fast_fi_entries[1]).unwrap(),
+            text
+        );
+    }
+}
@@ -1,24 +1,77 @@
+pub use archive_edit::{
+    edit_fiflfs_archive, edit_fiflfs_streams, edit_install, edit_zzz, EntryEdit,
+};
+pub use archive_reader::{ArchiveCursor, ArchiveReader};
+pub use cache::{
+    default_cache_directory, ArchiveCache, CacheLoad, CacheStatus, FileFingerprint,
+    CACHE_SCHEMA_VERSION,
+};
+pub use checksum::{ChecksumManifest, ChecksumReport, ChecksumWriter, FileChecksum};
+pub use error::{ArchiveError, ArchiveResult, ErrorLocation};
+pub use extract::{copy_zzz_entry, extract_zzz_entry, FileCopier, FsSource};
+pub use fiflfs_writer::{FIFLFSStreams, FIFLFSWriter};
+pub use listing::{archive_listing, write_listing, ListingEntry, ListingFormat};
+pub use lzss::{CompressionLevel, LzssReader};
+pub use manifest::{ExtractManifest, ManifestEntry, OutputStatus, SourceFingerprint};
 pub use oviiirs_archive::{
-    capitalize, display_directory_info, filter_valid_directories, find_archives,
-    find_archives_field, generate_new_filename, generate_new_filename_custom_extension,
-    generate_zzz_filename, load_bincode_from_file, load_toml_from_file, lz4_decompress,
-    process_files_in_directory, read_bytes_from_file, read_bytes_from_memory,
-    read_compressed_bytes_from_file_at_offset_lz4, read_compressed_bytes_from_file_at_offset_lzss,
-    read_compressed_bytes_from_memory_at_offset_lzss, read_data_from_file, save_bincode, save_toml,
-    write_bytes_to_file, CompressionTypeT, DirectorySelection,
+    backup_file, capitalize, display_directory_info, filter_valid_directories, find_archives,
+    find_archives_field, find_loose_archives, generate_new_filename,
+    generate_new_filename_custom_extension, generate_zzz_filename, load_bincode_from_file,
+    load_toml_from_file, lz4_compress_framed, lz4_decompress, lz4_decompress_framed,
+    lzss_decompress, parse_fi_entries, parse_fl_entries, process_files_in_directory,
+    read_bytes_from_file, read_bytes_from_memory, read_compressed_bytes_from_file_at_offset_lz4,
+    read_compressed_bytes_from_file_at_offset_lzss,
+    read_compressed_bytes_from_memory_at_offset_lzss, read_data_from_file, read_data_from_reader,
+    read_entry_bytes_from_memory, read_entry_bytes_from_reader, save_bincode, save_toml,
+    write_bytes_to_file, write_file_atomically, CompressionTypeT, DirectorySelection,
+};
+pub use plan::{ExtractionPlan, IncrementalReport, PlanConflict, PlannedFile};
+pub use query::{FileFilter, ParseQueryError, Query, SizeComparison};
+pub use release::{detect_release, GameRelease, ReleaseInfo};
+pub use steam::{
+    discover_install_directories, find_steam_installs, parse_vdf, steam_libraries, steam_roots,
+    ParseVdfError, SteamInstall, VdfValue, FF8_REMASTERED_APP_ID, FF8_STEAM_APP_ID,
 };
-mod lzss;
+pub use vfs::{
+    archive_files, ArchiveFile, ArchiveFileSource, ArchiveFs, VfsDirEntry, VfsMetadata, VirtualFs,
+};
+pub use zzz_writer::ZZZWriter;
+pub mod archive_edit;
+pub mod archive_reader;
+pub mod cache;
+pub mod checksum;
+pub mod error;
+pub mod extract;
+pub mod fiflfs_writer;
+pub mod listing;
+pub mod lzss;
+pub mod manifest;
+pub mod plan;
+pub mod query;
+pub mod release;
+pub mod steam;
+#[cfg(test)]
+mod test_util;
+pub mod vfs;
+pub mod zzz_writer;
 pub mod oviiirs_archive {
+    use crate::archive_reader::ArchiveReader;
+    use crate::error::{ArchiveError, ArchiveResult, ErrorLocation, ResultExt};
+    use crate::release::{detect_release, ReleaseInfo};
+    #[cfg(test)]
+    use crate::test_util::TempDir;
     use bincode;
     use core::fmt;
     use serde::de::DeserializeOwned;
     use serde::{Deserialize, Serialize};
+    use std::collections::BTreeMap;
     use std::collections::HashMap;
     use std::fs;
     use std::fs::File;
     use std::io;
     use std::io::BufRead;
     use std::io::BufReader;
+    #[cfg(test)]
     use std::io::Cursor;
     use std::io::Read;
     use std::io::Seek;
@@ -88,7 +141,7 @@ pub mod oviiirs_archive {
         }
     }
 
-    trait GenerateWindowsPath {
+    pub trait GenerateWindowsPath {
         fn generate_windows_path(&self) -> String;
         fn generate_windows_path_with_prefix(&self) -> String;
     }
@@ -123,6 +176,10 @@ pub mod oviiirs_archive {
     pub struct ZZZfiles {
         pub main: Option<ZZZHeader>,
         pub other: Option<ZZZHeader>,
+        // Holds the loose fi/fl/fs archives of the 2000 and Steam 2013 releases. The header has no
+        // entries and its file_path is the install directory.
+        #[serde(default)]
+        pub loose: Option<ZZZHeader>,
     }
 
     impl ZZZfiles {
@@ -153,7 +210,7 @@ pub mod oviiirs_archive {
         type IntoIter = std::vec::IntoIter<Self::Item>;
 
         fn into_iter(self) -> Self::IntoIter {
-            vec![self.main.as_ref(), self.other.as_ref()].into_iter()
+            vec![self.main.as_ref(), self.other.as_ref(), self.loose.as_ref()].into_iter()
         }
     }
 
@@ -185,6 +242,22 @@ pub mod oviiirs_archive {
         }
     }
 
+    impl TryFrom<u32> for CompressionTypeT {
+        type Error = ArchiveError;
+
+        fn try_from(code: u32) -> Result<Self, Self::Error> {
+            match code {
+                0 => Ok(CompressionTypeT::None),
+                1 => Ok(CompressionTypeT::Lzss),
+                2 => Ok(CompressionTypeT::Lz4),
+                _ => Err(ArchiveError::BadCompressionCode {
+                    location: ErrorLocation::default(),
+                    code,
+                }),
+            }
+        }
+    }
+
     impl fmt::Display for CompressionTypeT {
         fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
             match self {
@@ -195,6 +268,34 @@ pub mod oviiirs_archive {
         }
     }
 
+    #[derive(Debug)]
+    pub struct ParseCompressionTypeError(String);
+
+    impl fmt::Display for ParseCompressionTypeError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(
+                f,
+                "Unknown compression type \"{}\", expected none, lzss or lz4",
+                self.0
+            )
+        }
+    }
+
+    impl std::error::Error for ParseCompressionTypeError {}
+
+    impl std::str::FromStr for CompressionTypeT {
+        type Err = ParseCompressionTypeError;
+
+        fn from_str(s: &str) -> Result<Self, ParseCompressionTypeError> {
+            match s.trim().to_lowercase().as_str() {
+                "none" => Ok(CompressionTypeT::None),
+                "lzss" => Ok(CompressionTypeT::Lzss),
+                "lz4" => Ok(CompressionTypeT::Lz4),
+                _ => Err(ParseCompressionTypeError(s.to_string())),
+            }
+        }
+    }
+
     #[derive(Debug, Serialize, Deserialize, Clone)]
     pub enum ZZZArchiveType {
         None,
@@ -345,41 +446,33 @@ pub mod oviiirs_archive {
 
     impl ReadEntry for ZZZEntry {
         fn read_entry<R: Read>(reader: &mut R) -> io::Result<Self> {
-            let mut result = ZZZEntry::default();
-
-            result.string_length =
-                bincode::deserialize(&read_bytes(reader, std::mem::size_of::<u32>())?).map_err(
-                    |err| match *err {
-                        bincode::ErrorKind::Io(io_err) => io_err,
-                        _ => io::Error::new(io::ErrorKind::InvalidData, err),
-                    },
-                )?;
-
-            let string_data_bytes = read_bytes(reader, result.string_length as usize)?;
-            result.string_data = String::from_utf8(string_data_bytes).map_err(|err| match err {
-                _ => io::Error::new(io::ErrorKind::InvalidData, err),
-            })?;
-
-            result.file_offset =
-                bincode::deserialize(&read_bytes(reader, std::mem::size_of::<u64>())?).map_err(
-                    |err| match *err {
-                        bincode::ErrorKind::Io(io_err) => io_err,
-                        _ => io::Error::new(io::ErrorKind::InvalidData, err),
-                    },
-                )?;
-
-            result.file_size =
-                bincode::deserialize(&read_bytes(reader, std::mem::size_of::<u32>())?).map_err(
-                    |err| match *err {
-                        bincode::ErrorKind::Io(io_err) => io_err,
-                        _ => io::Error::new(io::ErrorKind::InvalidData, err),
-                    },
-                )?;
-
-            Ok(result)
+            Ok(read_zzz_entry(reader)?)
         }
     }
 
+    fn read_zzz_entry<R: Read>(reader: &mut R) -> ArchiveResult<ZZZEntry> {
+        let mut read_field = |size: usize| {
+            read_bytes(reader, size).map_err(|err| ArchiveError::from_read(err, "zzz entry"))
+        };
+        let string_length = u32::from_le_bytes(read_field(4)?.try_into().unwrap());
+        let string_data =
+            String::from_utf8(read_field(string_length as usize)?).map_err(|source| {
+                ArchiveError::InvalidPath {
+                    location: ErrorLocation::default(),
+                    source,
+                }
+            })?;
+        let file_offset = u64::from_le_bytes(read_field(8)?.try_into().unwrap());
+        let file_size = u32::from_le_bytes(read_field(4)?.try_into().unwrap());
+        Ok(ZZZEntry {
+            string_length,
+            string_data,
+            file_offset,
+            file_size,
+            compression_type: CompressionTypeT::None,
+        })
+    }
+
     impl WriteEntry for ZZZEntry {
         fn write_entry<W: Write>(&self, writer: &mut W) -> io::Result<()> {
             // Check if string length matches string data length
@@ -449,7 +542,7 @@ pub mod oviiirs_archive {
         }
     }
 
-    trait ReadEntries: ReadEntry {
+    pub trait ReadEntries: ReadEntry {
         fn read_entries<R: Read>(reader: &mut R) -> io::Result<Vec<Self>> {
             Self::read_entries_with_limit(reader, usize::MAX)
         }
@@ -470,10 +563,7 @@ pub mod oviiirs_archive {
                                 // println!("Cursor is already at the end of the data.");
                                 break;
                             }
-                            _ => {
-                                eprintln!("Error occurred: {}", error);
-                                Err(error)
-                            }
+                            _ => Err(error),
                         };
                     }
                 }
@@ -486,7 +576,7 @@ pub mod oviiirs_archive {
     impl ReadEntry for FI {}
     impl ReadEntries for FI {}
 
-    trait BufReadEntries: BufReadEntry {
+    pub trait BufReadEntries: BufReadEntry {
         fn read_entries<R: BufRead>(reader: &mut R) -> io::Result<Vec<Self>> {
             let mut vec: Vec<Self> = vec![];
             loop {
@@ -500,10 +590,7 @@ pub mod oviiirs_archive {
                                 //println!("Cursor is already at the end of the data.");
                                 Ok(vec)
                             }
-                            _ => {
-                                eprintln!("Error occurred: {}", error);
-                                Err(error)
-                            }
+                            _ => Err(error),
                         };
                     }
                 }
@@ -610,6 +697,253 @@ pub mod oviiirs_archive {
         assert_eq!(data_to_write, read_data);
     }
 
+    #[test]
+    fn test_find_loose_archives() {
+        let install = TempDir::new("loose");
+        let lang_en = install.join("Data").join("lang-en");
+        fs::create_dir_all(&lang_en).unwrap();
+        for (directory, stem, extensions) in [
+            (&lang_en, "field", ["fi", "fl", "fs"].as_slice()),
+            (
+                &install.join("Data"),
+                "battle",
+                ["fi", "fl", "fs"].as_slice(),
+            ),
+            (&install.join("Data"), "magic", ["fi", "fl"].as_slice()),
+        ] {
+            for extension in extensions {
+                fs::write(directory.join(format!("{}.{}", stem, extension)), b"1234").unwrap();
+            }
+        }
+
+        let archives = find_loose_archives(&install.to_str().unwrap().to_string()).unwrap();
+
+        assert_eq!(archives.len(), 2);
+        let battle = &archives[0];
+        assert_eq!(battle.archive_type, ArchiveType::Battle);
+        assert_eq!(battle.fs.string_data, "Data\\battle.fs");
+        assert_eq!(battle.fs.file_offset, 0);
+        assert_eq!(battle.fs.file_size, 4);
+        assert!(battle.fi_file_path().ends_with("battle.fi"));
+        assert_eq!(battle.file_path, battle.fs_file_path());
+
+        let field = &archives[1];
+        assert_eq!(field.archive_type, ArchiveType::Field);
+        assert_eq!(field.language.to_string(), "en");
+        assert_eq!(field.fl.string_data, "Data\\lang-en\\field.fl");
+    }
+
+    #[test]
+    fn test_parse_zzz_from_memory() {
+        let mut fiflfs_writer = crate::FIFLFSWriter::new();
+        fiflfs_writer
+            .add_bytes(
+                "ff8\\data\\eng\\menu\\a.bin",
+                b"first".to_vec(),
+                CompressionTypeT::None,
+            )
+            .unwrap();
+        fiflfs_writer
+            .add_bytes(
+                "ff8\\data\\eng\\menu\\b.bin",
+                b"second".repeat(20),
+                CompressionTypeT::Lzss,
+            )
+            .unwrap();
+        let mut zzz_writer = crate::ZZZWriter::new();
+        fiflfs_writer
+            .add_to_zzz(&mut zzz_writer, "data\\lang-en\\menu")
+            .unwrap();
+        let mut bytes = Vec::new();
+        zzz_writer.write(&mut bytes).unwrap();
+
+        let mut cursor = Cursor::new(bytes);
+        let header = read_data_from_reader(&mut cursor).unwrap();
+        assert_eq!(header.count, 3);
+        let archives = find_archives(header.entries, &"memory".to_string());
+        assert_eq!(archives.len(), 1);
+        let archive = &archives[0];
+
+        let fi_file =
+            FIfile::from_zzz_entry_and_reader(&archive.fi, &mut cursor, "memory").unwrap();
+        let fl_file =
+            FLfile::from_zzz_entry_and_reader(&archive.fl, &mut cursor, "memory").unwrap();
+        assert_eq!(fi_file.entries.len(), 2);
+        assert_eq!(&*fl_file.entries[1], "c:\\ff8\\data\\eng\\menu\\b.bin");
+
+        let fi = &fi_file.entries[1];
+        let entry = ZZZEntry {
+            file_offset: archive.fs.file_offset + fi.offset as u64,
+            file_size: fi.uncompressed_size,
+            compression_type: fi.compression_type,
+            ..Default::default()
+        };
+        assert_eq!(
+            read_entry_bytes_from_reader(&entry, &mut cursor).unwrap(),
+            b"second".repeat(20)
+        );
+    }
+
+    #[test]
+    fn test_corrupt_archive_errors() {
+        let mut zzz_writer = crate::ZZZWriter::new();
+        zzz_writer.add_bytes("a.bin", vec![1, 2, 3]).unwrap();
+        zzz_writer.add_bytes("b.bin", vec![4, 5, 6]).unwrap();
+        let mut bytes = Vec::new();
+        let entries = zzz_writer.write(&mut bytes).unwrap();
+
+        // Cut inside the second entry of the table.
+        let cut = entries[0].string_data.len() + 20;
+        match read_data_from_reader(&mut &bytes[..cut]) {
+            Err(ArchiveError::TruncatedHeader { location, .. }) => {
+                assert_eq!(location.entry_index, Some(1));
+                assert_eq!(location.offset, Some(4 + 4 + 5 + 8 + 4));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let mut cursor = Cursor::new(bytes);
+        let beyond = ZZZEntry {
+            file_offset: entries[1].file_offset,
+            file_size: 100,
+            ..Default::default()
+        };
+        let err = read_entry_bytes_from_reader(&beyond, &mut cursor).unwrap_err();
+        assert!(matches!(
+            err,
+            ArchiveError::EntryOutOfBounds { available: 3, .. }
+        ));
+        assert!(err.is_corrupt());
+
+        let mut fi = Vec::new();
+        fi.extend_from_slice(&[4, 0, 0, 0, 0, 0, 0, 0, 7, 0, 0, 0]);
+        match parse_fi_entries(&fi) {
+            Err(ArchiveError::BadCompressionCode { code: 7, location }) => {
+                assert_eq!(location.entry_index, Some(0))
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(matches!(
+            parse_fi_entries(&fi[..11]),
+            Err(ArchiveError::TruncatedHeader { .. })
+        ));
+        assert!(matches!(
+            parse_fl_entries(b"c:\\ok\r\nc:\\\xff\r\n"),
+            Err(ArchiveError::InvalidPath { location, .. }) if location.entry_index == Some(1)
+        ));
+
+        // Sizes from the data are checked against what is there before anything is allocated.
+        // One entry whose name claims to be 4GiB long.
+        let huge_string = [1u8, 0, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF, 0, 0];
+        assert!(matches!(
+            read_data_from_reader(&mut &huge_string[..]),
+            Err(ArchiveError::TruncatedHeader { .. })
+        ));
+        assert!(matches!(
+            read_compressed_bytes_from_memory_at_offset_lzss(&[0xFF, 0xFF, 0xFF, 0x7F, 1], 0),
+            Err(ArchiveError::EntryOutOfBounds { available: 1, .. })
+        ));
+        assert!(matches!(
+            read_compressed_bytes_from_memory_at_offset_lzss(&[1, 0], 1),
+            Err(ArchiveError::TruncatedHeader { .. })
+        ));
+        // A size below the 8 bytes of marker and uncompressed size used to underflow.
+        assert!(matches!(
+            read_compressed_bytes_from_memory_at_offset_lz4(
+                &[4, 0, 0, 0, b'4', b'Z', b'L', b'_', 0, 0, 0, 0],
+                0
+            ),
+            Err(ArchiveError::Decompression { .. })
+        ));
+        assert!(read_bytes_from_memory(&[1, 2, 3], 2, 2).is_err());
+        for compression_type in [CompressionTypeT::Lzss, CompressionTypeT::Lz4] {
+            let fs_bytes = crate::fiflfs_writer::frame_entry(b"abc", compression_type).unwrap();
+            let fi = FI {
+                uncompressed_size: u32::MAX,
+                offset: 0,
+                compression_type,
+            };
+            assert!(matches!(
+                read_entry_bytes_from_memory(&fs_bytes, &fi),
+                Err(ArchiveError::Decompression { .. })
+            ));
+        }
+
+        let err = read_data_from_file(&"does_not_exist.zzz".to_string()).unwrap_err();
+        assert!(err.is_not_found());
+        assert_eq!(err.location().path.as_deref(), Some("does_not_exist.zzz"));
+    }
+
+    #[test]
+    fn test_lz4_framed() {
+        let data = b"Balamb Garden ".repeat(50);
+        let framed = lz4_compress_framed(&data).unwrap();
+        assert_eq!(&framed[4..8], b"4ZL_");
+        assert_eq!(framed[..4], (framed.len() as u32 - 4).to_le_bytes());
+        assert_eq!(framed[8..12], (data.len() as u32).to_le_bytes());
+        assert_eq!(
+            lz4_decompress_framed(&framed, data.len() as u32).unwrap(),
+            data
+        );
+
+        // The header has to agree with the fi.
+        let fi = FI {
+            uncompressed_size: data.len() as u32 - 1,
+            offset: 0,
+            compression_type: CompressionTypeT::Lz4,
+        };
+        assert!(lz4_decompress_framed(&framed, fi.uncompressed_size).is_err());
+        assert!(read_entry_bytes_from_memory(&framed, &fi).is_err());
+
+        let mut bad_marker = framed.clone();
+        bad_marker[4] = b'5';
+        assert!(matches!(
+            lz4_decompress_framed(&bad_marker, data.len() as u32),
+            Err(ArchiveError::Decompression { .. })
+        ));
+        assert!(lz4_decompress_framed(&framed[..framed.len() - 1], data.len() as u32).is_err());
+    }
+
+    #[test]
+    fn test_config_load_errors_and_saves() {
+        let directory = TempDir::new("config");
+        let config_path = directory.join("config.toml").to_str().unwrap().to_string();
+
+        let err = load_toml_from_file::<Config>(&config_path).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+
+        let broken = "extract_regex_filter = \"\"\n[locations]\nchosen_directory = ff8\n";
+        fs::write(&config_path, broken).unwrap();
+        let err = load_toml_from_file::<Config>(&config_path).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err
+            .to_string()
+            .starts_with(&format!("{}:3:20: ", config_path)));
+
+        let backup_path = backup_file(&config_path).unwrap();
+        assert_eq!(backup_path, format!("{}.bak", config_path));
+        assert_eq!(
+            backup_file(&config_path).unwrap(),
+            format!("{}.bak2", config_path)
+        );
+        assert_eq!(fs::read_to_string(&backup_path).unwrap(), broken);
+
+        let mut config = Config::default();
+        config.locations.chosen_directory = "ff8".to_string();
+        save_toml(&config, &config_path).unwrap();
+        let loaded: Config = load_toml_from_file(&config_path).unwrap();
+        assert_eq!(loaded.locations.chosen_directory, "ff8");
+        let mut names: Vec<String> = fs::read_dir(&directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        assert_eq!(
+            names,
+            ["config.toml", "config.toml.bak", "config.toml.bak2"]
+        );
+    }
+
     pub trait WriteEntry: Serialize {
         fn write_entry<W: Write>(&self, writer: &mut W) -> io::Result<()> {
             // Serialize self into bytes
@@ -650,36 +984,84 @@ pub mod oviiirs_archive {
     impl WriteEntry for FI {}
 
     pub trait ConvertFromZZZEntryAndFile: Sized {
-        fn from_zzz_entry_and_file(entry: &ZZZEntry, file_path: &str) -> io::Result<Self>;
+        fn from_zzz_entry_and_file(entry: &ZZZEntry, file_path: &str) -> ArchiveResult<Self> {
+            let mut file = File::open(file_path).with_path(file_path)?;
+            Self::from_zzz_entry_and_reader(entry, &mut file, file_path)
+        }
+
+        // Parses the entry out of any seekable source, file_path is recorded in the result and
+        // in any error.
+        fn from_zzz_entry_and_reader<R: Read + Seek>(
+            entry: &ZZZEntry,
+            reader: &mut R,
+            file_path: &str,
+        ) -> ArchiveResult<Self>;
     }
 
     impl ConvertFromZZZEntryAndFile for FIfile {
-        fn from_zzz_entry_and_file(entry: &ZZZEntry, file_path: &str) -> io::Result<Self> {
+        fn from_zzz_entry_and_reader<R: Read + Seek>(
+            entry: &ZZZEntry,
+            reader: &mut R,
+            file_path: &str,
+        ) -> ArchiveResult<Self> {
             // Technically you don't need to always read the whole fi into memory except when it or it's parents are compressed. Just a simplication to load it into memory. You could always calculate the position from the fl file. Index*12 = the offset of an entry.
-            let buffer = match entry.compression_type {
-                CompressionTypeT::None => {
-                    read_bytes_from_file(file_path, entry.file_offset, entry.file_size as u64)?
-                }
-                CompressionTypeT::Lzss => crate::lzss::decompress(
-                    &read_compressed_bytes_from_file_at_offset_lzss(&file_path, entry.file_offset)?,
-                    entry.file_size as usize,
-                ),
-                CompressionTypeT::Lz4 => lz4_decompress(
-                    &read_compressed_bytes_from_file_at_offset_lz4(&file_path, entry.file_offset)?,
-                    entry.file_size as usize,
-                )?,
-            };
-            let mut cursor = io::Cursor::new(buffer);
+            let buffer = read_entry_bytes_from_reader(entry, reader).with_path(file_path)?;
 
             // Create a FIfile struct to hold the entries
             Ok(FIfile {
                 file_path: file_path.to_owned(),
-                entries: FI::read_entries(&mut cursor)?,
+                entries: parse_fi_entries(&buffer)
+                    .with_offset(entry.file_offset)
+                    .with_path(file_path)?,
                 //..Default::default() // Add this if you have other fields in FLfile
             })
         }
     }
 
+    // Splits decompressed fi data into its 12 byte records.
+    pub fn parse_fi_entries(bytes: &[u8]) -> ArchiveResult<Vec<FI>> {
+        let record_size = std::mem::size_of::<FI>();
+        if !bytes.len().is_multiple_of(record_size) {
+            return Err(ArchiveError::TruncatedHeader {
+                location: ErrorLocation::default(),
+                section: "fi record",
+            }
+            .with_entry_index(bytes.len() / record_size));
+        }
+        bytes
+            .chunks_exact(record_size)
+            .enumerate()
+            .map(|(index, record)| {
+                let field = |at: usize| u32::from_le_bytes(record[at..at + 4].try_into().unwrap());
+                Ok(FI {
+                    uncompressed_size: field(0),
+                    offset: field(4),
+                    compression_type: CompressionTypeT::try_from(field(8))
+                        .with_entry_index(index)?,
+                })
+            })
+            .collect()
+    }
+
+    // Splits decompressed fl data into its lines.
+    pub fn parse_fl_entries(bytes: &[u8]) -> ArchiveResult<Vec<FL>> {
+        bytes
+            .split_inclusive(|byte| *byte == b'\n')
+            .enumerate()
+            .map(|(index, line)| {
+                String::from_utf8(line.to_vec())
+                    .map(|line| line.trim().to_string().into())
+                    .map_err(|source| {
+                        ArchiveError::InvalidPath {
+                            location: ErrorLocation::default(),
+                            source,
+                        }
+                        .with_entry_index(index)
+                    })
+            })
+            .collect()
+    }
+
     pub trait BufReadEntry: DeserializeOwned {
         fn read_entry<R: BufRead>(reader: &mut R) -> io::Result<Self>;
     }
@@ -694,39 +1076,24 @@ pub mod oviiirs_archive {
                     "Unexpected end of file",
                 )),
                 Ok(_) => Ok(buffer.trim().to_string().into()),
-                Err(error) => {
-                    eprintln!("Error: {}", error);
-                    Err(error)
-                }
+                Err(error) => Err(error),
             }
         }
     }
 
     impl ConvertFromZZZEntryAndFile for FLfile {
-        fn from_zzz_entry_and_file(entry: &ZZZEntry, file_path: &str) -> io::Result<FLfile> {
-            // Open the file specified by file_path for reading
-            let buffer_bytes = match entry.compression_type {
-                CompressionTypeT::None => {
-                    read_bytes_from_file(file_path, entry.file_offset, entry.file_size as u64)?
-                }
-                CompressionTypeT::Lzss => crate::lzss::decompress(
-                    &read_compressed_bytes_from_file_at_offset_lzss(&file_path, entry.file_offset)?,
-                    entry.file_size as usize,
-                ),
-                CompressionTypeT::Lz4 => lz4_decompress(
-                    &read_compressed_bytes_from_file_at_offset_lz4(&file_path, entry.file_offset)?,
-                    entry.file_size as usize,
-                )?,
-            };
-
-            let cursor = Cursor::new(buffer_bytes);
-
-            // Initialize a BufReader for efficient reading
-            let mut reader = BufReader::new(cursor);
+        fn from_zzz_entry_and_reader<R: Read + Seek>(
+            entry: &ZZZEntry,
+            reader: &mut R,
+            file_path: &str,
+        ) -> ArchiveResult<FLfile> {
+            let buffer_bytes = read_entry_bytes_from_reader(entry, reader).with_path(file_path)?;
 
             Ok(FLfile {
                 file_path: file_path.to_owned(),
-                entries: FL::read_entries(&mut reader)?,
+                entries: parse_fl_entries(&buffer_bytes)
+                    .with_offset(entry.file_offset)
+                    .with_path(file_path)?,
                 //..Default::default() // Add this if you have other fields in FLfile
             })
         }
@@ -783,6 +1150,12 @@ pub mod oviiirs_archive {
         #[serde(default)]
         pub locations: Locations,
         pub extract_regex_filter: String,
+        /// Queries picking the files to extract, see `query::Query`. Every file when empty.
+        #[serde(default)]
+        pub include: Vec<String>,
+        /// Queries for files never extracted, even when an include rule matches them.
+        #[serde(default)]
+        pub exclude: Vec<String>,
     }
 
     #[derive(Serialize, Deserialize, Default, Clone)]
@@ -793,6 +1166,9 @@ pub mod oviiirs_archive {
         pub extract_directory: String,
         #[serde(default)]
         pub directories: Vec<String>,
+        /// The release found in each of the directories, keyed by directory.
+        #[serde(default)]
+        pub releases: BTreeMap<String, ReleaseInfo>,
     }
 
     fn default_extract_directory() -> String {
@@ -823,6 +1199,40 @@ pub mod oviiirs_archive {
         pub fi_file: Option<FIfile>,
         pub fl_file: Option<FLfile>,
         pub field_archives: Option<Vec<FIFLFSZZZ>>,
+        #[serde(default)]
+        pub loose_files: Option<FIFLFSLooseFiles>,
+    }
+
+    // The fi, fl and fs of the 2000 and Steam 2013 releases are separate files on disk instead of
+    // entries in a ZZZ. Their entries have an offset of 0 into these files.
+    #[derive(Debug, Serialize, Deserialize, Default, Clone)]
+    pub struct FIFLFSLooseFiles {
+        pub fi: String,
+        pub fl: String,
+        pub fs: String,
+    }
+
+    impl FIFLFSZZZ {
+        // File holding the fi. Same as file_path unless the archive is loose.
+        pub fn fi_file_path(&self) -> &str {
+            self.loose_files
+                .as_ref()
+                .map_or(&self.file_path, |loose| &loose.fi)
+        }
+
+        // File holding the fl. Same as file_path unless the archive is loose.
+        pub fn fl_file_path(&self) -> &str {
+            self.loose_files
+                .as_ref()
+                .map_or(&self.file_path, |loose| &loose.fl)
+        }
+
+        // File holding the fs. Same as file_path unless the archive is loose.
+        pub fn fs_file_path(&self) -> &str {
+            self.loose_files
+                .as_ref()
+                .map_or(&self.file_path, |loose| &loose.fs)
+        }
     }
 
     impl FIFLFSZZZTemp {
@@ -841,6 +1251,7 @@ pub mod oviiirs_archive {
                 fi_file: None,
                 fl_file: None,
                 field_archives: None,
+                loose_files: None,
             }
         }
     }
@@ -875,6 +1286,38 @@ pub mod oviiirs_archive {
                 }
             }
         }
+
+        // Detects the release of the directories that have none saved yet, and forgets the ones
+        // of directories that are no longer listed.
+        pub fn detect_releases(&mut self) {
+            let directories = &self.directories;
+            self.releases
+                .retain(|directory, _| directories.contains(directory));
+            for directory in filter_valid_directories(&self.directories) {
+                if !self.releases.contains_key(&directory) {
+                    self.detect_release(&directory);
+                }
+            }
+        }
+
+        // Detects the release of one directory again, like after choosing it.
+        pub fn detect_release(&mut self, directory: &str) {
+            match detect_release(directory) {
+                Ok(info) => {
+                    self.releases.insert(directory.to_string(), info);
+                }
+                Err(err) => eprintln!("Could not detect the release in {}: {}", directory, err),
+            }
+        }
+
+        // Adds the directories that are not saved yet, like the installs found through Steam.
+        pub fn add_directories<I: IntoIterator<Item = String>>(&mut self, directories: I) {
+            for directory in directories {
+                if !self.directories.contains(&directory) {
+                    self.directories.push(directory);
+                }
+            }
+        }
     }
 
     pub enum DirectorySelection {
@@ -883,6 +1326,7 @@ pub mod oviiirs_archive {
         Exit,
     }
 
+    // The number of each option is what is typed to choose it, so new options go at the end.
     #[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
     pub enum MainMenuSelection {
         ChangeFF8Directory,
@@ -891,6 +1335,8 @@ pub mod oviiirs_archive {
         ChangeRegExFilter,
         RebuildCache,
         Exit,
+        ReplaceFileInArchive,
+        ExtractChangedFiles,
     }
 
     #[derive(Debug)]
@@ -910,6 +1356,8 @@ pub mod oviiirs_archive {
                     MainMenuSelection::ChangeRegExFilter => "Change RegEx Filter",
                     MainMenuSelection::RebuildCache => "Rebuild Cache",
                     MainMenuSelection::Exit => "Exit",
+                    MainMenuSelection::ReplaceFileInArchive => "Replace File In Archive",
+                    MainMenuSelection::ExtractChangedFiles => "Extract Changed Files",
                 }
             )
         }
@@ -938,6 +1386,12 @@ pub mod oviiirs_archive {
                 s if s == format!("{}", MainMenuSelection::Exit as u32) => {
                     Ok(MainMenuSelection::Exit)
                 }
+                s if s == format!("{}", MainMenuSelection::ReplaceFileInArchive as u32) => {
+                    Ok(MainMenuSelection::ReplaceFileInArchive)
+                }
+                s if s == format!("{}", MainMenuSelection::ExtractChangedFiles as u32) => {
+                    Ok(MainMenuSelection::ExtractChangedFiles)
+                }
                 _ => Err(ParseMainMenuError::InvalidInput(s.to_string())),
             }
         }
@@ -951,46 +1405,89 @@ pub mod oviiirs_archive {
     }
 
     fn read_file_contents_as_string(config_path: &String) -> io::Result<String> {
-        fs::read_to_string(config_path).or_else(|_| {
-            eprintln!("Could not read file `{}`", config_path);
-            Ok(String::new()) // Return an empty string in case of an error
-        })
+        fs::read_to_string(config_path)
+            .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", config_path, err)))
     }
 
+    // Parse errors are InvalidData errors naming the file, and for TOML the line and column.
     pub fn load_toml_from_file<T>(config_path: &String) -> io::Result<T>
     where
-        T: DeserializeOwned + Default,
+        T: DeserializeOwned,
     {
         // Read the contents of the configuration file
         let contents = read_file_contents_as_string(config_path)?;
 
         // Attempt to parse the content into the specified type (T)
-        match toml::from_str::<T>(&contents) {
-            Ok(data) => Ok(data),
-            Err(_) => {
-                // Handle the error case, you can choose to return a default instance of T or propagate the error.
-                // For example, return a default instance of T:
-                Ok(Default::default())
-            }
-        }
+        toml::from_str::<T>(&contents).map_err(|err| {
+            let location = err
+                .span()
+                .map(|span| {
+                    let before = &contents[..span.start];
+                    let line = before.matches('\n').count() + 1;
+                    let column = before.len() - before.rfind('\n').map_or(0, |index| index + 1) + 1;
+                    format!(":{}:{}", line, column)
+                })
+                .unwrap_or_default();
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}{}: {}", config_path, location, err.message()),
+            )
+        })
     }
 
     pub fn load_bincode_from_file<T>(config_path: &str) -> io::Result<T>
     where
-        T: DeserializeOwned + Default,
+        T: DeserializeOwned,
     {
         // Read the contents of the configuration file as binary data
         let contents = read_file_contents_as_vec_u8(config_path)?;
 
         // Attempt to deserialize the binary content into the specified type (T)
-        match bincode::deserialize::<T>(&contents) {
-            Ok(data) => Ok(data),
-            Err(_) => {
-                // Handle the error case, you can choose to return a default instance of T or propagate the error.
-                // For example, return a default instance of T:
-                Ok(Default::default())
-            }
+        bincode::deserialize::<T>(&contents).map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", config_path, err),
+            )
+        })
+    }
+
+    /// Copies a file that could not be loaded next to it, as `<name>.bak`, or `<name>.bak2` and
+    /// so on when older backups exist, so saving over it loses nothing. Returns the backup path.
+    pub fn backup_file(path: &str) -> io::Result<String> {
+        let mut backup_path = format!("{}.bak", path);
+        let mut number = 1;
+        while Path::new(&backup_path).exists() {
+            number += 1;
+            backup_path = format!("{}.bak{}", path, number);
         }
+        fs::copy(path, &backup_path)?;
+        Ok(backup_path)
+    }
+
+    /// Writes `contents` to a temporary file next to `path` and renames it over `path`, so a
+    /// failed or interrupted save leaves the previous file intact.
+    pub fn write_file_atomically(path: &Path, contents: &[u8]) -> io::Result<()> {
+        let file_name = path.file_name().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is not a file path", path.display()),
+            )
+        })?;
+        let temp_path = path.with_file_name(format!(
+            ".{}.{}.tmp",
+            file_name.to_string_lossy(),
+            std::process::id()
+        ));
+        let result = File::create(&temp_path)
+            .and_then(|mut file| {
+                file.write_all(contents)?;
+                file.sync_all()
+            })
+            .and_then(|_| fs::rename(&temp_path, path));
+        if result.is_err() {
+            let _ = fs::remove_file(&temp_path);
+        }
+        result
     }
 
     fn read_file_contents_as_vec_u8(file_path: &str) -> io::Result<Vec<u8>> {
@@ -1023,6 +1520,7 @@ pub mod oviiirs_archive {
 
     pub fn display_directory_info(
         directories: &Vec<String>,
+        releases: &BTreeMap<String, ReleaseInfo>,
         previously_chosen_directory: &String,
     ) -> DirectorySelection {
         loop {
@@ -1031,7 +1529,10 @@ pub mod oviiirs_archive {
                 println!("    None...");
             } else {
                 for (index, dir_path) in directories.iter().enumerate() {
-                    println!(" {:>3}: {}", index + 1, dir_path);
+                    match releases.get(dir_path) {
+                        Some(release) => println!(" {:>3}: {} ({})", index + 1, dir_path, release),
+                        None => println!(" {:>3}: {}", index + 1, dir_path),
+                    }
                 }
             }
 
@@ -1117,25 +1618,11 @@ pub mod oviiirs_archive {
     where
         T: Serialize,
     {
-        let config_str = toml::to_string(config).map_err(|e| {
-            io::Error::new(
-                io::ErrorKind::Other,
-                format!("Failed to serialize updated toml: {}", e),
-            )
-        })?;
+        let config_str = toml::to_string(config)
+            .map_err(|e| io::Error::other(format!("Failed to serialize updated toml: {}", e)))?;
 
-        let mut file = fs::File::create(filename).map_err(|e| {
-            io::Error::new(
-                io::ErrorKind::Other,
-                format!("Failed to create the specified file: {}", e),
-            )
-        })?;
-
-        let mut buf_writer = io::BufWriter::new(&mut file);
-
-        buf_writer.write_all(config_str.as_bytes())?;
-        buf_writer.flush()?;
-        Ok(())
+        write_file_atomically(Path::new(filename), config_str.as_bytes())
+            .map_err(|e| io::Error::new(e.kind(), format!("Failed to write {}: {}", filename, e)))
     }
 
     pub fn save_bincode<T>(config: &T, filename: &str) -> Result<(), io::Error>
@@ -1143,25 +1630,11 @@ pub mod oviiirs_archive {
         T: Serialize,
     {
         // Serialize the configuration using bincode
-        let config_bytes = bincode::serialize(config).map_err(|e| {
-            io::Error::new(
-                io::ErrorKind::Other,
-                format!("Failed to serialize updated bincode: {}", e),
-            )
-        })?;
-
-        // Create or open the specified file
-        let mut file = fs::File::create(filename).map_err(|e| {
-            io::Error::new(
-                io::ErrorKind::Other,
-                format!("Failed to create the specified file: {}", e),
-            )
-        })?;
-
-        // Write the serialized data to the file
-        file.write_all(&config_bytes)?;
+        let config_bytes = bincode::serialize(config)
+            .map_err(|e| io::Error::other(format!("Failed to serialize updated bincode: {}", e)))?;
 
-        Ok(())
+        write_file_atomically(Path::new(filename), &config_bytes)
+            .map_err(|e| io::Error::new(e.kind(), format!("Failed to write {}: {}", filename, e)))
     }
 
     pub fn process_files_in_directory(directory: &String) -> io::Result<Vec<String>> {
@@ -1185,7 +1658,122 @@ pub mod oviiirs_archive {
         Ok(files)
     }
 
-    pub fn read_data_from_file(file_path: &String) -> io::Result<ZZZHeader> {
+    // Loose archives sit in the install directory, in Data and in Data/lang-xx.
+    fn loose_archive_directories(directory: &Path) -> io::Result<Vec<PathBuf>> {
+        let mut directories = vec![directory.to_path_buf()];
+        let mut index = 0;
+        while index < directories.len() {
+            let current = directories[index].clone();
+            let is_root = index == 0;
+            index += 1;
+            for entry in fs::read_dir(&current)? {
+                let path = entry?.path();
+                if !path.is_dir() {
+                    continue;
+                }
+                let name = path
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .unwrap_or_default()
+                    .to_lowercase();
+                if (is_root && name == "data") || name.starts_with("lang-") {
+                    directories.push(path);
+                }
+            }
+        }
+        Ok(directories)
+    }
+
+    pub fn find_loose_archives(directory: &String) -> io::Result<Vec<FIFLFSZZZ>> {
+        let root = Path::new(directory);
+        let mut groups: HashMap<String, [Option<PathBuf>; 3]> = HashMap::new();
+
+        for loose_directory in loose_archive_directories(root)? {
+            for entry in fs::read_dir(&loose_directory)? {
+                let path = entry?.path();
+                if !path.is_file() {
+                    continue;
+                }
+                let index = match path
+                    .extension()
+                    .and_then(|extension| extension.to_str())
+                    .map(|extension| extension.to_lowercase())
+                    .as_deref()
+                {
+                    Some("fi") => 0,
+                    Some("fl") => 1,
+                    Some("fs") => 2,
+                    _ => continue,
+                };
+                let key = path.with_extension("").to_string_lossy().to_lowercase();
+                groups.entry(key).or_default()[index] = Some(path);
+            }
+        }
+
+        let to_entry = |path: &PathBuf| -> io::Result<(String, ZZZEntry)> {
+            let invalid_path = || {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Path is not valid UTF-8: {}", path.display()),
+                )
+            };
+            let file_path = path.to_str().ok_or_else(invalid_path)?.to_string();
+            let string_data = path
+                .strip_prefix(root)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?
+                .to_str()
+                .ok_or_else(invalid_path)?
+                .generate_windows_path();
+            let file_size = u32::try_from(fs::metadata(path)?.len()).map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{} is larger than 4GiB", file_path),
+                )
+            })?;
+            Ok((
+                file_path,
+                ZZZEntry {
+                    string_length: string_data.len() as u32,
+                    string_data,
+                    file_offset: 0,
+                    file_size,
+                    compression_type: CompressionTypeT::None,
+                },
+            ))
+        };
+
+        let mut archives = Vec::new();
+        for group in groups.into_values() {
+            let [Some(fi), Some(fl), Some(fs)] = group else {
+                continue;
+            };
+            let (fi_path, fi) = to_entry(&fi)?;
+            let (fl_path, fl) = to_entry(&fl)?;
+            let (fs_path, fs) = to_entry(&fs)?;
+            let lowercase_string_data = fi.string_data.to_lowercase();
+            let path_buf = Utf8WindowsPath::new(&lowercase_string_data);
+            archives.push(FIFLFSZZZ {
+                file_path: fs_path.clone(),
+                language: get_language_code(&path_buf),
+                archive_type: get_archive_type(&path_buf),
+                fi,
+                fl,
+                fs,
+                fi_file: None,
+                fl_file: None,
+                field_archives: None,
+                loose_files: Some(FIFLFSLooseFiles {
+                    fi: fi_path,
+                    fl: fl_path,
+                    fs: fs_path,
+                }),
+            });
+        }
+        archives.sort_by(|a, b| a.fs.string_data.cmp(&b.fs.string_data));
+        Ok(archives)
+    }
+
+    pub fn read_data_from_file(file_path: &String) -> ArchiveResult<ZZZHeader> {
         let archive_type = match Utf8TypedPath::derive(file_path) {
             Utf8TypedPath::Unix(unix_path_buf) => {
                 // Handle Unix path
@@ -1197,26 +1785,48 @@ pub mod oviiirs_archive {
             }
         };
 
-        let mut file = File::open(file_path)?;
+        let file = File::open(file_path).with_path(file_path)?;
+        let mut header = read_data_from_reader(&mut BufReader::new(file)).with_path(file_path)?;
+        header.file_path = file_path.to_string();
+        header.archive_type = archive_type;
+        Ok(header)
+    }
 
+    // Reads the count and entry table of a zzz. file_path and archive_type are left for the
+    // caller to fill in since a reader has no name.
+    pub fn read_data_from_reader<R: Read>(reader: &mut R) -> ArchiveResult<ZZZHeader> {
         // Read the 32-bit count from the file
         let mut count_bytes = [0u8; 4];
-        file.read_exact(&mut count_bytes)?;
+        reader
+            .read_exact(&mut count_bytes)
+            .map_err(|err| ArchiveError::from_read(err, "zzz entry count").with_offset(0))?;
         let count = u32::from_le_bytes(count_bytes);
 
         // Deserialize the entries
+        let mut offset = count_bytes.len() as u64;
+        let mut entries = Vec::new();
+        for index in 0..count as usize {
+            let entry = read_zzz_entry(reader)
+                .with_entry_index(index)
+                .with_offset(offset)?;
+            offset += 4 + entry.string_data.len() as u64 + 8 + 4;
+            entries.push(entry);
+        }
         Ok(ZZZHeader {
-            file_path: file_path.to_string(),
-            archive_type,
             count,
-            entries: ZZZEntry::read_entries(&mut file)?,
-            fiflfs_files: None,
+            entries,
+            ..Default::default()
         })
     }
 
+    // The buffer grows as data arrives instead of trusting length, so a damaged size fails at
+    // the end of the data rather than allocating gigabytes up front.
     fn read_bytes<R: Read>(reader: &mut R, length: usize) -> io::Result<Vec<u8>> {
-        let mut buffer = vec![0; length];
-        reader.read_exact(&mut buffer)?;
+        let mut buffer = Vec::new();
+        reader.take(length as u64).read_to_end(&mut buffer)?;
+        if buffer.len() != length {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+        }
         Ok(buffer)
     }
 
@@ -1257,14 +1867,32 @@ pub mod oviiirs_archive {
         new_filename
     }
 
-    pub fn find_archives_field(archive: &FIFLFSZZZ) -> io::Result<Vec<FIFLFSZZZ>> {
+    pub fn find_archives_field(archive: &FIFLFSZZZ) -> ArchiveResult<Vec<FIFLFSZZZ>> {
+        find_archives_field_from_readers(
+            archive,
+            &mut File::open(archive.fi_file_path()).with_path(archive.fi_file_path())?,
+            &mut File::open(archive.fl_file_path()).with_path(archive.fl_file_path())?,
+        )
+    }
+
+    // Same as find_archives_field with the fi and fl read from the given sources, which are
+    // positioned like the files the entries point into.
+    pub fn find_archives_field_from_readers<FIR: Read + Seek, FLR: Read + Seek>(
+        archive: &FIFLFSZZZ,
+        fi_reader: &mut FIR,
+        fl_reader: &mut FLR,
+    ) -> ArchiveResult<Vec<FIFLFSZZZ>> {
         let mut archives: HashMap<String, FIFLFSZZZTemp> = HashMap::new();
 
-        let file_path = &archive.file_path;
+        // The nested archives live inside the fs.
+        let file_path = &archive.fs_file_path().to_string();
 
-        let fi_file = FIfile::from_zzz_entry_and_file(&archive.fi, &file_path)?;
+        let fi_file =
+            FIfile::from_zzz_entry_and_reader(&archive.fi, fi_reader, archive.fi_file_path())?;
 
-        let fl_file = FLfile::from_zzz_entry_and_file(&archive.fl, &file_path)?;
+        let fl_file =
+            FLfile::from_zzz_entry_and_reader(&archive.fl, fl_reader, archive.fl_file_path())?;
+        check_fi_fl_counts(&fi_file, &fl_file)?;
 
         let entries = fi_file.entries.iter().zip(&fl_file.entries);
 
@@ -1290,6 +1918,19 @@ pub mod oviiirs_archive {
             .collect())
     }
 
+    // Every fi record has a matching fl line, a different count means one of them is damaged.
+    pub fn check_fi_fl_counts(fi_file: &FIfile, fl_file: &FLfile) -> ArchiveResult<()> {
+        if fi_file.entries.len() != fl_file.entries.len() {
+            return Err(ArchiveError::CountMismatch {
+                location: ErrorLocation::default(),
+                fi_count: fi_file.entries.len(),
+                fl_count: fl_file.entries.len(),
+            }
+            .with_path(&fl_file.file_path));
+        }
+        Ok(())
+    }
+
     pub fn find_archives(entries: Vec<ZZZEntry>, file_path: &String) -> Vec<FIFLFSZZZ> {
         let mut archives: HashMap<String, FIFLFSZZZTemp> = HashMap::new();
 
@@ -1376,126 +2017,290 @@ pub mod oviiirs_archive {
     pub fn read_compressed_bytes_from_memory_at_offset_lzss(
         input_data: &[u8],
         offset: usize,
-    ) -> Vec<u8> {
-        // Ensure that the offset is within bounds
-        if offset >= input_data.len() {
-            return Vec::new(); // Return an empty vector if offset is out of bounds
-        }
-        // Deserialize a u32 from the file
-        let compressed_size_as_bytes: [u8; 4] =
-            bincode::deserialize(&input_data[offset..(offset + 4)]).unwrap();
-        let compressed_size = u32::from_le_bytes(compressed_size_as_bytes) as usize;
-
-        // Calculate the end index based on offset and size
-        let start_index = offset + 4;
-        let end_index = start_index + compressed_size.min(input_data.len() - start_index);
-        input_data[start_index..end_index].to_vec()
+    ) -> ArchiveResult<Vec<u8>> {
+        read_compressed_bytes_from_reader_at_offset_lzss(
+            &mut io::Cursor::new(input_data),
+            offset as u64,
+        )
     }
 
     pub fn read_compressed_bytes_from_file_at_offset_lzss(
-        file_path: &str,
+        file: &ArchiveReader,
         offset: u64,
-    ) -> io::Result<Vec<u8>> {
-        // Open the file
-        let mut file = File::open(file_path)?;
-
-        // Move to the specified offset
-        file.seek(SeekFrom::Start(offset))?;
-
-        // Deserialize a u32 from the file
-        let size_as_bytes: [u8; 4] = bincode::deserialize(&read_bytes(&mut file, 4)?).unwrap();
-        let size = u32::from_le_bytes(size_as_bytes);
-
-        // Read the specified number of bytes following the offset
-        let mut buffer = vec![0; size as usize];
-        file.read_exact(&mut buffer)?;
-
-        Ok(buffer)
+    ) -> ArchiveResult<Vec<u8>> {
+        file.read_compressed_bytes_lzss(offset)
     }
 
     pub fn read_compressed_bytes_from_memory_at_offset_lz4(
         input_data: &[u8],
         offset: usize,
-    ) -> Vec<u8> {
-        // Ensure that the offset is within bounds
-        if offset >= input_data.len() {
-            return Vec::new(); // Return an empty vector if offset is out of bounds
-        }
-        // Deserialize a u32 from the file
-        let compressed_size_as_bytes: [u8; 4] =
-            bincode::deserialize(&input_data[offset..(offset + 4)]).unwrap();
-        let compressed_size = u32::from_le_bytes(compressed_size_as_bytes) as usize - 8;
-
-        // Calculate the end index based on offset and size
-        let start_index = offset + 12;
-        let end_index = start_index + compressed_size.min(input_data.len() - start_index);
-
-        input_data[12..end_index].to_vec()
+    ) -> ArchiveResult<Vec<u8>> {
+        read_compressed_bytes_from_reader_at_offset_lz4(
+            &mut io::Cursor::new(input_data),
+            offset as u64,
+        )
     }
 
     pub fn read_compressed_bytes_from_file_at_offset_lz4(
-        file_path: &str,
+        file: &ArchiveReader,
         offset: u64,
-    ) -> io::Result<Vec<u8>> {
-        // Open the file
-        let mut file = File::open(file_path)?;
-
-        // Move to the specified offset
-        file.seek(SeekFrom::Start(offset))?;
-
-        // Deserialize a u32 from the file
-        let compressed_size_as_bytes: [u8; 4] =
-            bincode::deserialize(&read_bytes(&mut file, 4)?).unwrap();
-        let compressed_size = u32::from_le_bytes(compressed_size_as_bytes) - 8;
-
-        file.seek(SeekFrom::Current(4))?;
-
-        let uncompressed_size_as_bytes: [u8; 4] =
-            bincode::deserialize(&read_bytes(&mut file, 4)?).unwrap();
-        let _uncompressed_size = u32::from_le_bytes(uncompressed_size_as_bytes);
-
-        //file.seek(SeekFrom::Current(8))?;
+    ) -> ArchiveResult<Vec<u8>> {
+        file.read_compressed_bytes_lz4(offset)
+    }
 
-        // Read the specified number of bytes following the offset
-        let mut buffer = vec![0; compressed_size as usize];
-        file.read_exact(&mut buffer)?;
+    // Function to read bytes from a file at a specified offset. The reader is taken rather than
+    // a path so reading many entries of the same file opens it once.
+    pub fn read_bytes_from_file(
+        file: &ArchiveReader,
+        offset: u64,
+        size: u64,
+    ) -> ArchiveResult<Vec<u8>> {
+        file.read_bytes(offset, size)
+    }
+
+    // Reads the entry at its offset in file, decompressing it if needed.
+    pub fn read_entry_bytes_from_file(
+        entry: &ZZZEntry,
+        file: &ArchiveReader,
+    ) -> ArchiveResult<Vec<u8>> {
+        file.read_entry_bytes(entry)
+    }
+
+    // Fails before anything is allocated when the range does not fit in the source.
+    fn check_bounds<R: Seek>(reader: &mut R, offset: u64, size: u64) -> ArchiveResult<()> {
+        let len = reader.seek(SeekFrom::End(0))?;
+        if offset.checked_add(size).is_none_or(|end| end > len) {
+            return Err(ArchiveError::EntryOutOfBounds {
+                location: ErrorLocation::default(),
+                size,
+                available: len.saturating_sub(offset),
+            }
+            .with_offset(offset));
+        }
+        Ok(())
+    }
 
+    fn read_header<R: Read + Seek, const N: usize>(
+        reader: &mut R,
+        offset: u64,
+        section: &'static str,
+    ) -> ArchiveResult<[u8; N]> {
+        let mut header = [0u8; N];
+        reader.seek(SeekFrom::Start(offset))?;
+        reader
+            .read_exact(&mut header)
+            .map_err(|err| ArchiveError::from_read(err, section).with_offset(offset))?;
+        Ok(header)
+    }
+
+    pub fn read_bytes_from_reader<R: Read + Seek>(
+        reader: &mut R,
+        offset: u64,
+        size: u64,
+    ) -> ArchiveResult<Vec<u8>> {
+        check_bounds(reader, offset, size)?;
+        reader.seek(SeekFrom::Start(offset))?;
+        let mut buffer = vec![0u8; size as usize];
+        reader
+            .read_exact(&mut buffer)
+            .map_err(|err| ArchiveError::from_read(err, "entry").with_offset(offset))?;
         Ok(buffer)
     }
 
-    // Function to read bytes from a file at a specified offset
-    pub fn read_bytes_from_file(
-        file_path: &str,
+    pub fn read_compressed_bytes_from_reader_at_offset_lzss<R: Read + Seek>(
+        reader: &mut R,
         offset: u64,
-        size: u64,
-    ) -> Result<Vec<u8>, io::Error> {
-        // Open the file
-        let mut file = File::open(file_path)?;
+    ) -> ArchiveResult<Vec<u8>> {
+        let size = u32::from_le_bytes(read_header(reader, offset, "lzss header")?);
+        read_bytes_from_reader(reader, offset + 4, size as u64)
+    }
 
-        // Seek to the specified offset
-        file.seek(SeekFrom::Start(offset))?;
+    pub fn read_compressed_bytes_from_reader_at_offset_lz4<R: Read + Seek>(
+        reader: &mut R,
+        offset: u64,
+    ) -> ArchiveResult<Vec<u8>> {
+        Ok(read_lz4_block(reader, offset)?.0)
+    }
 
-        // Read the specified number of bytes
-        let mut buffer = vec![0u8; size as usize];
-        file.read_exact(&mut buffer)?;
+    // Reads the LZ4 block at offset along with the uncompressed size its header records.
+    fn read_lz4_block<R: Read + Seek>(
+        reader: &mut R,
+        offset: u64,
+    ) -> ArchiveResult<(Vec<u8>, u32)> {
+        let header = read_header(reader, offset, "lz4 header")?;
+        let (block_size, uncompressed_size) = parse_lz4_header(&header).with_offset(offset)?;
+        let block =
+            read_bytes_from_reader(reader, offset + LZ4_HEADER_SIZE as u64, block_size as u64)?;
+        Ok((block, uncompressed_size))
+    }
+
+    // The Remaster stores LZ4 entries as the size of everything after it, the marker, the
+    // uncompressed size and then the block.
+    const LZ4_HEADER_SIZE: usize = 12;
+    const LZ4_MARKER: [u8; 4] = *b"4ZL_";
+
+    // Returns the size of the block and the uncompressed size recorded in the header.
+    fn parse_lz4_header(header: &[u8; LZ4_HEADER_SIZE]) -> ArchiveResult<(u32, u32)> {
+        let declared_size = u32::from_le_bytes(header[..4].try_into().unwrap());
+        let block_size =
+            declared_size
+                .checked_sub(8)
+                .ok_or_else(|| ArchiveError::Decompression {
+                    location: ErrorLocation::default(),
+                    message: format!("LZ4 header declares only {} bytes", declared_size),
+                })?;
+        if header[4..8] != LZ4_MARKER {
+            return Err(ArchiveError::Decompression {
+                location: ErrorLocation::default(),
+                message: format!(
+                    "LZ4 header has marker {:02X?} instead of 4ZL_",
+                    &header[4..8]
+                ),
+            });
+        }
+        Ok((
+            block_size,
+            u32::from_le_bytes(header[8..].try_into().unwrap()),
+        ))
+    }
+
+    // The fi and the LZ4 header both record the uncompressed size, they have to agree.
+    fn check_lz4_uncompressed_size(recorded: u32, expected: u32) -> ArchiveResult<()> {
+        if recorded != expected {
+            return Err(ArchiveError::Decompression {
+                location: ErrorLocation::default(),
+                message: format!(
+                    "LZ4 header records {} uncompressed bytes but {} are expected",
+                    recorded, expected
+                ),
+            });
+        }
+        Ok(())
+    }
 
-        Ok(buffer)
+    // Same as read_entry_bytes_from_file for any seekable source.
+    pub fn read_entry_bytes_from_reader<R: Read + Seek>(
+        entry: &ZZZEntry,
+        reader: &mut R,
+    ) -> ArchiveResult<Vec<u8>> {
+        // Empty entries are placeholders, their offset does not have to point anywhere.
+        if entry.file_size == 0 {
+            return Ok(Vec::new());
+        }
+        Ok(match entry.compression_type {
+            CompressionTypeT::None => {
+                read_bytes_from_reader(reader, entry.file_offset, entry.file_size as u64)?
+            }
+            CompressionTypeT::Lzss => lzss_decompress(
+                &read_compressed_bytes_from_reader_at_offset_lzss(reader, entry.file_offset)?,
+                entry.file_size as usize,
+            )
+            .with_offset(entry.file_offset)?,
+            CompressionTypeT::Lz4 => {
+                let (block, uncompressed_size) = read_lz4_block(reader, entry.file_offset)?;
+                check_lz4_uncompressed_size(uncompressed_size, entry.file_size)
+                    .and_then(|()| lz4_decompress(&block, entry.file_size as usize))
+                    .with_offset(entry.file_offset)?
+            }
+        })
     }
 
-    pub fn read_bytes_from_memory(input_data: &[u8], offset: usize, size: usize) -> Vec<u8> {
-        // Ensure that the offset is within bounds
-        if offset >= input_data.len() {
-            return Vec::new(); // Return an empty vector if offset is out of bounds
+    // Reads the entry described by fi out of an already decompressed fs.
+    pub fn read_entry_bytes_from_memory(fs_bytes: &[u8], fi: &FI) -> ArchiveResult<Vec<u8>> {
+        let entry = ZZZEntry {
+            file_offset: fi.offset as u64,
+            file_size: fi.uncompressed_size,
+            compression_type: fi.compression_type,
+            ..Default::default()
+        };
+        read_entry_bytes_from_reader(&entry, &mut io::Cursor::new(fs_bytes))
+    }
+
+    pub fn read_bytes_from_memory(
+        input_data: &[u8],
+        offset: usize,
+        size: usize,
+    ) -> ArchiveResult<Vec<u8>> {
+        read_bytes_from_reader(&mut io::Cursor::new(input_data), offset as u64, size as u64)
+    }
+
+    // The most each format can expand. An LZSS flag byte and eight 2 byte references give 144
+    // bytes from 17, an LZ4 match costs at least one byte per 255 bytes of length.
+    const LZSS_MAX_EXPANSION: usize = 9;
+    const LZ4_MAX_EXPANSION: usize = 255;
+
+    // A declared size the compressed data could never produce is corrupt, rejecting it keeps
+    // the decoders from reserving memory for it.
+    fn check_uncompressed_size(
+        compressed_size: usize,
+        size: usize,
+        max_expansion: usize,
+    ) -> ArchiveResult<()> {
+        let limit = compressed_size
+            .saturating_mul(max_expansion)
+            .saturating_add(16);
+        if size > limit {
+            return Err(ArchiveError::Decompression {
+                location: ErrorLocation::default(),
+                message: format!(
+                    "{} compressed bytes can not hold the {} bytes declared",
+                    compressed_size, size
+                ),
+            });
         }
+        Ok(())
+    }
 
-        // Calculate the end index based on offset and size
-        let end_index = offset + size.min(input_data.len() - offset);
+    pub fn lzss_decompress(input_data: &[u8], size: usize) -> ArchiveResult<Vec<u8>> {
+        check_uncompressed_size(input_data.len(), size, LZSS_MAX_EXPANSION)?;
+        Ok(crate::lzss::decompress_strict(input_data, size)?)
+    }
 
-        // Create a Vec<u8> from the sliced portion
-        input_data[offset..end_index].to_vec()
+    pub fn lz4_decompress(input_data: &[u8], size: usize) -> ArchiveResult<Vec<u8>> {
+        check_uncompressed_size(input_data.len(), size, LZ4_MAX_EXPANSION)?;
+        let size = i32::try_from(size).map_err(|_| ArchiveError::Decompression {
+            location: ErrorLocation::default(),
+            message: format!("{} bytes is too large for an LZ4 block", size),
+        })?;
+        lz4::block::decompress(input_data, Some(size)).map_err(|err| ArchiveError::Decompression {
+            location: ErrorLocation::default(),
+            message: err.to_string(),
+        })
     }
 
-    pub fn lz4_decompress(input_data: &[u8], size: usize) -> Result<Vec<u8>, io::Error> {
-        lz4::block::decompress(&input_data, Some(size as i32))
+    /// Compresses `data` into an LZ4 entry as the Remaster stores it in a fs: the header with
+    /// the marker and uncompressed size followed by the block.
+    pub fn lz4_compress_framed(data: &[u8]) -> ArchiveResult<Vec<u8>> {
+        let uncompressed_size = u32::try_from(data.len()).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} bytes is too large for an LZ4 entry", data.len()),
+            )
+        })?;
+        let block = lz4::block::compress(data, None, false)?;
+        let mut framed = Vec::with_capacity(LZ4_HEADER_SIZE + block.len());
+        framed.extend_from_slice(&(block.len() as u32 + 8).to_le_bytes());
+        framed.extend_from_slice(&LZ4_MARKER);
+        framed.extend_from_slice(&uncompressed_size.to_le_bytes());
+        framed.extend_from_slice(&block);
+        Ok(framed)
+    }
+
+    /// Decompresses one entry written by [`lz4_compress_framed`]. The uncompressed size in its
+    /// header must match `uncompressed_size`, usually `FI::uncompressed_size`.
+    pub fn lz4_decompress_framed(framed: &[u8], uncompressed_size: u32) -> ArchiveResult<Vec<u8>> {
+        let (block, recorded_size) = read_lz4_block(&mut io::Cursor::new(framed), 0)?;
+        if LZ4_HEADER_SIZE + block.len() != framed.len() {
+            return Err(ArchiveError::Decompression {
+                location: ErrorLocation::default(),
+                message: format!(
+                    "LZ4 entry is {} bytes but its header declares {}",
+                    framed.len(),
+                    LZ4_HEADER_SIZE + block.len()
+                ),
+            });
+        }
+        check_lz4_uncompressed_size(recorded_size, uncompressed_size)?;
+        lz4_decompress(&block, uncompressed_size as usize)
     }
 }
@@ -0,0 +1,272 @@
+use crate::oviiirs_archive::{CompressionTypeT, ZZZfiles, FIFLFSZZZ};
+use crate::vfs::{archive_files, ArchiveFile, ArchiveFileSource};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io;
+use std::io::Write;
+
+/// A file of the install as it is written by [`write_listing`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ListingEntry {
+    pub path: String,
+    /// The archives the file is nested in, outermost first.
+    pub archive_chain: Vec<String>,
+    pub language: String,
+    pub archive_type: String,
+    /// Where the stored data starts inside the innermost archive, the `.zzz` for its own entries
+    /// and the fs for fi/fl/fs entries.
+    pub offset: u64,
+    /// Bytes the file takes up in that archive, including the compression header.
+    pub compressed_size: u64,
+    pub uncompressed_size: u64,
+    pub compression_type: String,
+}
+
+impl ListingEntry {
+    fn new(file: &ArchiveFile, offset: u64, compressed_size: u64) -> Self {
+        ListingEntry {
+            path: file.path.to_string(),
+            archive_chain: file.archive_chain.iter().map(|s| s.to_string()).collect(),
+            language: file.language.to_string(),
+            archive_type: file.archive_type.to_string(),
+            offset,
+            compressed_size,
+            uncompressed_size: file.uncompressed_size(),
+            compression_type: file.compression_type().to_string(),
+        }
+    }
+}
+
+/// Lists every file of the install, in the order of [`archive_files`].
+pub fn archive_listing(zzz_files: &ZZZfiles) -> Vec<ListingEntry> {
+    let mut stored_sizes: Option<(&FIFLFSZZZ, Vec<u64>)> = None;
+    archive_files(zzz_files)
+        .iter()
+        .map(|file| match file.source {
+            ArchiveFileSource::ZZZ { entry, .. } => {
+                ListingEntry::new(file, entry.file_offset, entry.file_size as u64)
+            }
+            ArchiveFileSource::FIFLFS { archive, fi, index } => {
+                if !matches!(stored_sizes, Some((cached, _)) if std::ptr::eq(cached, archive)) {
+                    stored_sizes = Some((archive, fiflfs_stored_sizes(archive)));
+                }
+                let compressed_size = match fi.compression_type {
+                    CompressionTypeT::None => fi.uncompressed_size as u64,
+                    _ => stored_sizes.as_ref().map_or(0, |(_, sizes)| sizes[index]),
+                };
+                ListingEntry::new(file, fi.offset as u64, compressed_size)
+            }
+        })
+        .collect()
+}
+
+// The fi entries only record where the data starts, so the stored size of each is the distance to
+// the next entry in the fs, or to the end of the fs for the last one.
+fn fiflfs_stored_sizes(archive: &FIFLFSZZZ) -> Vec<u64> {
+    let Some(fi_file) = archive.fi_file.as_ref() else {
+        return Vec::new();
+    };
+    let mut offsets: Vec<u64> = fi_file.entries.iter().map(|fi| fi.offset as u64).collect();
+    offsets.sort_unstable();
+    offsets.dedup();
+    let fs_size = archive.fs.file_size as u64;
+    fi_file
+        .entries
+        .iter()
+        .map(|fi| {
+            let offset = fi.offset as u64;
+            let next = offsets.partition_point(|&start| start <= offset);
+            offsets
+                .get(next)
+                .copied()
+                .unwrap_or(fs_size)
+                .saturating_sub(offset)
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ListingFormat {
+    /// One JSON array holding every entry.
+    #[default]
+    Json,
+    /// A header row, then one row per entry with the archive chain joined by `|`.
+    Csv,
+    /// One JSON object per line.
+    Ndjson,
+}
+
+impl fmt::Display for ListingFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ListingFormat::Json => write!(f, "json"),
+            ListingFormat::Csv => write!(f, "csv"),
+            ListingFormat::Ndjson => write!(f, "ndjson"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ParseListingFormatError(String);
+
+impl fmt::Display for ParseListingFormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Unknown listing format \"{}\", expected json, csv or ndjson",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for ParseListingFormatError {}
+
+impl std::str::FromStr for ListingFormat {
+    type Err = ParseListingFormatError;
+
+    fn from_str(s: &str) -> Result<Self, ParseListingFormatError> {
+        match s.trim().to_lowercase().as_str() {
+            "json" => Ok(ListingFormat::Json),
+            "csv" => Ok(ListingFormat::Csv),
+            "ndjson" => Ok(ListingFormat::Ndjson),
+            _ => Err(ParseListingFormatError(s.to_string())),
+        }
+    }
+}
+
+pub fn write_listing<W: Write>(
+    entries: &[ListingEntry],
+    format: ListingFormat,
+    mut writer: W,
+) -> io::Result<()> {
+    match format {
+        ListingFormat::Json => {
+            serde_json::to_writer_pretty(&mut writer, entries)?;
+            writeln!(writer)?;
+        }
+        ListingFormat::Ndjson => {
+            for entry in entries {
+                serde_json::to_writer(&mut writer, entry)?;
+                writeln!(writer)?;
+            }
+        }
+        ListingFormat::Csv => {
+            let mut csv_writer = csv::Writer::from_writer(&mut writer);
+            csv_writer.write_record([
+                "path",
+                "archive_chain",
+                "language",
+                "archive_type",
+                "offset",
+                "compressed_size",
+                "uncompressed_size",
+                "compression_type",
+            ])?;
+            for entry in entries {
+                csv_writer.write_record([
+                    entry.path.clone(),
+                    entry.archive_chain.join("|"),
+                    entry.language.clone(),
+                    entry.archive_type.clone(),
+                    entry.offset.to_string(),
+                    entry.compressed_size.to_string(),
+                    entry.uncompressed_size.to_string(),
+                    entry.compression_type.clone(),
+                ])?;
+            }
+            csv_writer.flush()?;
+        }
+    }
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fiflfs_writer::FIFLFSWriter;
+    use crate::test_util::{load_zzz, TempDir};
+    use crate::zzz_writer::ZZZWriter;
+
+    #[test]
+    fn test_archive_listing_formats() {
+        let directory = TempDir::new("listing");
+        let zzz_path = directory.join("main.zzz").to_str().unwrap().to_string();
+
+        let mut menu_writer = FIFLFSWriter::new();
+        menu_writer
+            .add_bytes(
+                "c:\\ff8\\data\\eng\\menu\\Mngrp.bin",
+                b"menu groups".repeat(20),
+                CompressionTypeT::Lzss,
+            )
+            .unwrap();
+        menu_writer
+            .add_bytes(
+                "c:\\ff8\\data\\eng\\menu\\face\\face1.tex",
+                b"faces".to_vec(),
+                CompressionTypeT::None,
+            )
+            .unwrap();
+        let mut zzz_writer = ZZZWriter::new();
+        zzz_writer
+            .add_bytes("data\\music\\dmusic\\ogg\\00.ogg", b"OggS".to_vec())
+            .unwrap();
+        menu_writer
+            .add_to_zzz(&mut zzz_writer, "data\\lang-en\\menu")
+            .unwrap();
+        zzz_writer.write_to_file(&zzz_path).unwrap();
+        let fs_bytes = menu_writer.to_streams().unwrap().fs;
+
+        let mut zzz_files = ZZZfiles::default();
+        assert!(zzz_files.push(load_zzz(&zzz_path)));
+
+        let entries = archive_listing(&zzz_files);
+        let menu = entries
+            .iter()
+            .find(|entry| entry.path.ends_with("Mngrp.bin"))
+            .unwrap();
+        assert_eq!(menu.language, "en");
+        assert_eq!(menu.archive_type, "Menu");
+        assert_eq!(menu.offset, 0);
+        assert_eq!(menu.uncompressed_size, 220);
+        assert_eq!(menu.compression_type, "lzss");
+        let face = entries
+            .iter()
+            .find(|entry| entry.path.ends_with("face1.tex"))
+            .unwrap();
+        assert_eq!(menu.compressed_size, face.offset);
+        assert_eq!(face.offset + face.compressed_size, fs_bytes.len() as u64);
+        assert_eq!(
+            face.archive_chain,
+            vec![zzz_path.clone(), "data\\lang-en\\menu.fs".to_string()]
+        );
+        let ogg = entries
+            .iter()
+            .find(|entry| entry.path.ends_with("00.ogg"))
+            .unwrap();
+        assert_eq!((ogg.language.as_str(), ogg.compressed_size), ("none", 4));
+
+        let mut json = Vec::new();
+        write_listing(&entries, ListingFormat::Json, &mut json).unwrap();
+        let parsed: Vec<ListingEntry> = serde_json::from_slice(&json).unwrap();
+        assert_eq!(parsed, entries);
+
+        let mut ndjson = Vec::new();
+        write_listing(&entries, ListingFormat::Ndjson, &mut ndjson).unwrap();
+        let parsed: Vec<ListingEntry> = String::from_utf8(ndjson)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(parsed, entries);
+
+        let mut csv = Vec::new();
+        write_listing(&entries, ListingFormat::Csv, &mut csv).unwrap();
+        let mut reader = csv::Reader::from_reader(csv.as_slice());
+        let rows: Vec<csv::StringRecord> = reader.records().map(|row| row.unwrap()).collect();
+        assert_eq!(rows.len(), entries.len());
+        let face_row = rows.iter().find(|row| row[0] == face.path).unwrap();
+        assert_eq!(&face_row[1], face.archive_chain.join("|"));
+        assert_eq!(&face_row[7], "none");
+    }
+}
@@ -1,8 +1,13 @@
-pub use lzss::{compress, decompress};
+pub use lzss::{
+    compress, compress_with_level, decompress, decompress_into, decompress_strict,
+    CompressionError, CompressionLevel, LzssReader, ParseCompressionLevelError,
+};
 
 pub mod lzss {
-    use log::{debug, error, info, trace, warn};
-    use std::{cell::RefCell, fmt};
+    use log::info;
+    use std::fmt;
+    use std::io;
+    use std::io::Read;
 
     const R_SIZE: usize = 4078;
     const MATCH_MASK: u32 = 0xF0;
@@ -23,38 +28,26 @@ pub mod lzss {
     const N_PLUS2: usize = N + 2;
     const N_PLUS17: usize = N + F_MINUS1;
 
-    fn decode_buffer(code_buf: &[u8]) -> String {
-        code_buf
-            .iter()
-            .map(|b| {
-                match b {
-                    // If the byte represents a valid ASCII character, add it to the decoded string
-                    0x20..=0x7E => (*b as char).to_string(),
-                    // For invalid bytes, print them as \x followed by their hexadecimal representation
-                    _ => format!("{:02X}", b).to_string(),
-                }
-            })
-            .collect::<Vec<String>>()
-            .join(", ")
-    }
-
     #[derive(Debug)]
     pub struct CompressionError {
         pub message: String,
+        /// How many compressed bytes had been read when the problem was found.
+        pub position: u64,
     }
 
     impl fmt::Display for CompressionError {
         fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-            write!(f, "{}", self.message)
+            write!(f, "{} at compressed byte {}", self.message, self.position)
         }
     }
 
     impl std::error::Error for CompressionError {}
 
     impl CompressionError {
-        fn new(message: &str) -> Self {
+        fn new(message: &str, position: u64) -> Self {
             CompressionError {
                 message: message.to_string(),
+                position,
             }
         }
     }
@@ -69,7 +62,6 @@ pub mod lzss {
     }
 
     impl CompressImpl {
-        #[allow(dead_code)]
         fn new() -> Self {
             let mut right_side = [0u32; RIGHT_SIDE_SIZE];
             right_side[N_PLUS1..].fill(NOT_USED);
@@ -112,21 +104,14 @@ pub mod lzss {
                     }
                 }
 
+                // Length of the common prefix, stopping at the first byte that differs.
                 let mut node_index: usize = 1;
-                for (new_index, new_cmp) in key
-                    .iter()
-                    .take(NODE_SIZE)
-                    .skip(1)
-                    .zip(self.text_buf.iter().skip(1 + p))
-                    .map(|(a, b)| *a as i16 - *b as i16)
-                    .enumerate()
-                    .map(|(i, c)| (i + 1, c))
-                {
-                    cmp = new_cmp;
-                    node_index = new_index;
-                    if new_cmp == 0 {
+                while node_index < NODE_SIZE {
+                    cmp = key[node_index] as i16 - self.text_buf[p + node_index] as i16;
+                    if cmp != 0 {
                         break;
                     }
+                    node_index += 1;
                 }
                 if node_index > self.match_length {
                     self.match_position = p;
@@ -190,7 +175,6 @@ pub mod lzss {
             self.parent[p] = NOT_USED;
         }
 
-        #[allow(dead_code)]
         fn compress(mut self, src: &[u8]) -> Vec<u8> {
             // should only be called once
             if src.iter().peekable().peek().is_none() {
@@ -240,11 +224,9 @@ pub mod lzss {
             let mut code_buf_ptr = 1;
             let mut mask = 1;
 
-            info!("Starting loop iteration...");
             loop {
                 if self.match_length > len {
                     self.match_length = len;
-                    debug!("Match length adjusted...");
                 }
 
                 if self.match_length <= 2 {
@@ -252,29 +234,17 @@ pub mod lzss {
                     code_buf[0] |= mask;
                     code_buf[code_buf_ptr] = self.text_buf[r];
                     code_buf_ptr += 1;
-                    debug!(
-                        "Match length is less than or equal to 2... \n\t\t{:?}\n\t\t{:?}",
-                        code_buf,
-                        decode_buffer(&code_buf)
-                    );
                 } else {
                     code_buf[code_buf_ptr] = self.match_position as u8;
                     code_buf_ptr += 1;
                     code_buf[code_buf_ptr] = ((self.match_position >> 4) as u32 & MATCH_MASK) as u8
                         | (self.match_length - (2 + 1)) as u8;
                     code_buf_ptr += 1;
-                    debug!(
-                        "Match length is greater than 2... \n\t\t{:?}\n\t\t{:?}",
-                        code_buf,
-                        decode_buffer(&code_buf)
-                    );
                 }
 
                 if (mask << 1) != 0 {
                     mask = mask << 1;
                 } else {
-                    info!("Mask reached maximum value...");
-
                     result.extend_from_slice(&code_buf[..code_buf_ptr]);
                     //assert_eq!(result, verify[..result.len()]);
 
@@ -284,12 +254,9 @@ pub mod lzss {
                     mask = 1;
                 }
 
-                info!("Result updated...");
-
                 let last_match_length = self.match_length;
                 let mut loop_count = 0;
 
-                info!("Entering match processing loop...");
                 for _ in 0..last_match_length {
                     let c = match data.next() {
                         Some(symbol) => symbol,
@@ -300,7 +267,10 @@ pub mod lzss {
                     self.delete_node(s);
                     self.text_buf[s] = *c;
 
-                    self.text_buf[s + N] = *c;
+                    // Mirror the start of the ring so matches can run past its end.
+                    if s < F_MINUS1 {
+                        self.text_buf[s + N] = *c;
+                    }
 
                     s = (s + 1) & N_MINUS1;
                     r = (r + 1) & N_MINUS1;
@@ -309,9 +279,6 @@ pub mod lzss {
                     loop_count += 1;
                 }
 
-                info!("Match processed...");
-
-                info!("Processing remaining matches...");
                 for _ in loop_count..last_match_length {
                     self.delete_node(s);
                     s = (s + 1) & N_MINUS1;
@@ -322,10 +289,7 @@ pub mod lzss {
                     }
                 }
 
-                info!("Remaining matches processed...");
-
                 if len == 0 {
-                    info!("No more data to compress. Exiting loop...");
                     break;
                 }
             }
@@ -345,70 +309,525 @@ pub mod lzss {
         }
     }
 
-    #[allow(dead_code)]
     pub fn compress(src: &[u8]) -> Vec<u8> {
         CompressImpl::new().compress(src)
     }
 
-    #[allow(dead_code)]
-    pub fn decompress(src: &[u8], dst_size: usize) -> Vec<u8> {
-        let mut dst = Vec::<u8>::new();
-        if dst_size > 0 {
-            dst.reserve(dst_size);
+    /// How hard the compressor searches for matches. Every level produces data the usual
+    /// decoder reads, only the size and the time it takes differ.
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+    pub enum CompressionLevel {
+        /// Takes the longest of the few most recent matches.
+        Fast,
+        /// Searches further back and holds a match for one byte when the next one is longer.
+        Balanced,
+        /// The binary tree search of [`compress`], the output is byte for byte the same.
+        #[default]
+        Exhaustive,
+    }
+
+    impl fmt::Display for CompressionLevel {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self {
+                CompressionLevel::Fast => write!(f, "fast"),
+                CompressionLevel::Balanced => write!(f, "balanced"),
+                CompressionLevel::Exhaustive => write!(f, "exhaustive"),
+            }
+        }
+    }
+
+    #[derive(Debug)]
+    pub struct ParseCompressionLevelError(String);
+
+    impl fmt::Display for ParseCompressionLevelError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(
+                f,
+                "Unknown compression level \"{}\", expected fast, balanced or exhaustive",
+                self.0
+            )
         }
+    }
 
-        let iterator = RefCell::new(src.iter());
-        let mut text_buf = [0u32; N_MINUS1 + F];
-        let mut r = N - F;
-        let mut flags = 0u32;
+    impl std::error::Error for ParseCompressionLevelError {}
 
-        let test_at_end = || iterator.borrow().as_slice().is_empty();
-        let next = || iterator.borrow_mut().next().cloned().unwrap_or(0u8);
+    impl std::str::FromStr for CompressionLevel {
+        type Err = ParseCompressionLevelError;
 
-        while !test_at_end() {
-            flags >>= 1;
-            if flags & FLAGS_MASK == 0 {
-                if test_at_end() {
-                    break;
+        fn from_str(s: &str) -> Result<Self, ParseCompressionLevelError> {
+            match s.trim().to_lowercase().as_str() {
+                "fast" => Ok(CompressionLevel::Fast),
+                "balanced" => Ok(CompressionLevel::Balanced),
+                "exhaustive" => Ok(CompressionLevel::Exhaustive),
+                _ => Err(ParseCompressionLevelError(s.to_string())),
+            }
+        }
+    }
+
+    pub fn compress_with_level(src: &[u8], level: CompressionLevel) -> Vec<u8> {
+        match level {
+            CompressionLevel::Fast => HashChainEncoder::new(src, 8, false).compress(),
+            CompressionLevel::Balanced => HashChainEncoder::new(src, 128, true).compress(),
+            CompressionLevel::Exhaustive => compress(src),
+        }
+    }
+
+    const HASH_BITS: u32 = 14;
+    const NO_POSITION: u32 = u32::MAX;
+    const MIN_MATCH: usize = THRESHOLD + 1;
+    // The encoder keeps the next F bytes in the ring, so references reach back at most N - F.
+    const MAX_DISTANCE: usize = N - F;
+
+    // Finds matches through chains of earlier positions that share a 3 byte hash.
+    struct HashChainEncoder<'a> {
+        src: &'a [u8],
+        head: Vec<u32>,
+        // The previous position with the same hash, indexed by position modulo N.
+        prev: Vec<u32>,
+        inserted: usize,
+        max_chain: usize,
+        lazy: bool,
+    }
+
+    impl<'a> HashChainEncoder<'a> {
+        fn new(src: &'a [u8], max_chain: usize, lazy: bool) -> Self {
+            HashChainEncoder {
+                src,
+                head: vec![NO_POSITION; 1 << HASH_BITS],
+                prev: vec![NO_POSITION; N],
+                inserted: 0,
+                max_chain,
+                lazy,
+            }
+        }
+
+        fn hash(&self, position: usize) -> usize {
+            let bytes = &self.src[position..position + MIN_MATCH];
+            let key = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], 0]);
+            (key.wrapping_mul(0x9E37_79B1) >> (32 - HASH_BITS)) as usize
+        }
+
+        // Adds every position before `end` to the chains.
+        fn insert_until(&mut self, end: usize) {
+            while self.inserted < end {
+                if self.inserted + MIN_MATCH <= self.src.len() {
+                    let hash = self.hash(self.inserted);
+                    self.prev[self.inserted & N_MINUS1] = self.head[hash];
+                    self.head[hash] = self.inserted as u32;
                 }
-                flags = next() as u32 | FLAGS_BITS;
+                self.inserted += 1;
             }
+        }
 
-            if flags & 1 == 1 {
-                if test_at_end() {
+        // The longest earlier match for the bytes at `position` as (length, source position).
+        fn find_match(&self, position: usize) -> (usize, usize) {
+            let max_length = F.min(self.src.len() - position);
+            let mut best = (0, 0);
+            if max_length < MIN_MATCH {
+                return best;
+            }
+            let target = &self.src[position..position + max_length];
+            let mut candidate = self.head[self.hash(position)];
+            for _ in 0..self.max_chain {
+                if candidate == NO_POSITION || position - candidate as usize > MAX_DISTANCE {
                     break;
                 }
-                let current = next() as u32;
-                dst.push(current as u8);
-                text_buf[r] = current;
-                r = (r + 1) & N_MINUS1;
-            } else {
-                if test_at_end() {
-                    break;
+                let candidate_position = candidate as usize;
+                // Only a match that also agrees on the byte after the best one so far can beat it.
+                if best.0 == 0 || self.src[candidate_position + best.0] == target[best.0] {
+                    // The source may run into the bytes being encoded, the decoder copies one
+                    // byte at a time so it sees them already written.
+                    let length = target
+                        .iter()
+                        .zip(&self.src[candidate_position..])
+                        .take_while(|(a, b)| a == b)
+                        .count();
+                    if length > best.0 {
+                        best = (length, candidate_position);
+                        if length == max_length {
+                            break;
+                        }
+                    }
                 }
-                let offset = next();
-                if test_at_end() {
+                let next = self.prev[candidate_position & N_MINUS1];
+                // Older slots of prev are reused, a chain never goes forward.
+                if next >= candidate {
                     break;
                 }
-                let mut count = next() as u32;
-                let offset = (offset as u32 | ((count as u32 & OFFSET_MASK) << 4)) as u32;
-                count = (count & COUNT_MASK) + THRESHOLD as u32;
-
-                for k in 0..=count {
-                    let current = text_buf[(offset as usize + k as usize) & N_MINUS1];
-                    dst.push(current as u8);
-                    text_buf[r] = current;
-                    r = (r + 1) & N_MINUS1;
+                candidate = next;
+            }
+            best
+        }
+
+        fn compress(mut self) -> Vec<u8> {
+            let mut result = Vec::with_capacity(self.src.len() / 2);
+            let mut code_buf = [0u8; F_MINUS1];
+            let mut code_buf_ptr = 1;
+            let mut mask = 1u8;
+            let mut position = 0;
+
+            while position < self.src.len() {
+                self.insert_until(position);
+                let (mut length, source) = self.find_match(position);
+                if self.lazy && (MIN_MATCH..F).contains(&length) {
+                    self.insert_until(position + 1);
+                    if self.find_match(position + 1).0 > length {
+                        length = 0;
+                    }
+                }
+
+                if length < MIN_MATCH {
+                    code_buf[0] |= mask;
+                    code_buf[code_buf_ptr] = self.src[position];
+                    code_buf_ptr += 1;
+                    position += 1;
+                } else {
+                    // References are positions in the decoder's ring, which starts at R_SIZE.
+                    let ring_position = (R_SIZE + source) & N_MINUS1;
+                    code_buf[code_buf_ptr] = ring_position as u8;
+                    code_buf[code_buf_ptr + 1] = ((ring_position >> 4) as u32 & MATCH_MASK) as u8
+                        | (length - MIN_MATCH) as u8;
+                    code_buf_ptr += 2;
+                    position += length;
+                }
+
+                if mask == 0x80 {
+                    result.extend_from_slice(&code_buf[..code_buf_ptr]);
+                    code_buf[0] = 0;
+                    code_buf_ptr = 1;
+                    mask = 1;
+                } else {
+                    mask <<= 1;
                 }
             }
+
+            if code_buf_ptr > 1 {
+                result.extend_from_slice(&code_buf[..code_buf_ptr]);
+            }
+            result
         }
+    }
 
+    pub fn decompress(src: &[u8], dst_size: usize) -> Vec<u8> {
+        let mut dst = Vec::<u8>::new();
+        // dst_size comes from the archive, never reserve more than src could expand to.
+        let max_size = src.len().saturating_mul(9);
+        if dst_size > 0 {
+            dst.reserve(dst_size.min(max_size));
+        }
+        // Reading from a slice can not fail.
+        LzssReader::new(src).read_to_end(&mut dst).unwrap();
         dst
     }
+
+    /// Decompresses `src` to exactly `dst_size` bytes, failing when `src` ends in the middle of a
+    /// reference or decodes to more or fewer bytes than that.
+    pub fn decompress_strict(src: &[u8], dst_size: usize) -> Result<Vec<u8>, CompressionError> {
+        let mut dst = Vec::with_capacity(dst_size.min(src.len().saturating_mul(9)));
+        // A slice only fails with the CompressionError put there by the reader.
+        LzssReader::strict(src, dst_size as u64)
+            .read_to_end(&mut dst)
+            .map_err(|err| *err.into_inner().unwrap().downcast().unwrap())?;
+        Ok(dst)
+    }
+
+    /// Decompresses `src` into `dst` and returns how many bytes were written. Output that does
+    /// not fit in `dst` is dropped.
+    pub fn decompress_into(src: &[u8], dst: &mut [u8]) -> usize {
+        let mut reader = LzssReader::new(src);
+        let mut written = 0;
+        while written < dst.len() {
+            match reader.read(&mut dst[written..]).unwrap() {
+                0 => break,
+                read => written += read,
+            }
+        }
+        written
+    }
+
+    const INPUT_BUFFER_SIZE: usize = 4096;
+
+    /// Decompresses LZSS data as it is read, so an entry can be copied to a file or handed to a
+    /// parser without holding both the compressed and decompressed bytes. The output ends where
+    /// the compressed data ends, use `take` to stop at the uncompressed size, or
+    /// [`LzssReader::strict`] to require it.
+    pub struct LzssReader<R: Read> {
+        inner: R,
+        input: Box<[u8; INPUT_BUFFER_SIZE]>,
+        input_position: usize,
+        input_len: usize,
+        text_buf: [u8; N],
+        r: usize,
+        flags: u32,
+        match_position: usize,
+        match_remaining: usize,
+        finished: bool,
+        // Set in strict mode, the number of bytes the data has to decompress to.
+        expected_size: Option<u64>,
+        produced: u64,
+        consumed: u64,
+    }
+
+    impl<R: Read> LzssReader<R> {
+        pub fn new(inner: R) -> Self {
+            LzssReader {
+                inner,
+                input: Box::new([0; INPUT_BUFFER_SIZE]),
+                input_position: 0,
+                input_len: 0,
+                text_buf: [0; N],
+                r: N - F,
+                flags: 0,
+                match_position: 0,
+                match_remaining: 0,
+                finished: false,
+                expected_size: None,
+                produced: 0,
+                consumed: 0,
+            }
+        }
+
+        /// Like [`LzssReader::new`], but reading fails with an `InvalidData` error holding a
+        /// [`CompressionError`] when the data is cut off in the middle of a reference or does
+        /// not decompress to exactly `expected_size` bytes.
+        pub fn strict(inner: R, expected_size: u64) -> Self {
+            LzssReader {
+                expected_size: Some(expected_size),
+                ..LzssReader::new(inner)
+            }
+        }
+
+        fn error(&mut self, message: &str) -> io::Error {
+            self.finished = true;
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                CompressionError::new(message, self.consumed),
+            )
+        }
+
+        // In strict mode, fails when writing `len` more bytes goes past the expected size.
+        fn check_overshoot(&mut self, len: usize) -> io::Result<()> {
+            match self.expected_size {
+                Some(expected_size) if self.produced + len as u64 > expected_size => Err(self
+                    .error(&format!(
+                        "Data decompresses past the {} bytes expected",
+                        expected_size
+                    ))),
+                _ => Ok(()),
+            }
+        }
+
+        // The data ran out at the end of a token. In strict mode the output must be complete.
+        fn finish(&mut self) -> io::Result<()> {
+            self.finished = true;
+            match self.expected_size {
+                Some(expected_size) if self.produced < expected_size => Err(self.error(&format!(
+                    "Data ends after {} of the {} bytes expected",
+                    self.produced, expected_size
+                ))),
+                _ => Ok(()),
+            }
+        }
+
+        pub fn into_inner(self) -> R {
+            self.inner
+        }
+
+        fn next_byte(&mut self) -> io::Result<Option<u8>> {
+            if self.input_position == self.input_len {
+                self.input_len = loop {
+                    match self.inner.read(&mut self.input[..]) {
+                        Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                        result => break result?,
+                    }
+                };
+                self.input_position = 0;
+                if self.input_len == 0 {
+                    return Ok(None);
+                }
+            }
+            self.input_position += 1;
+            self.consumed += 1;
+            Ok(Some(self.input[self.input_position - 1]))
+        }
+
+        fn push(&mut self, byte: u8) {
+            self.produced += 1;
+            self.text_buf[self.r] = byte;
+            self.r = (self.r + 1) & N_MINUS1;
+        }
+
+        // Reads the next flag bit, or the next flag byte once the current one is used up.
+        fn next_flag(&mut self) -> io::Result<Option<bool>> {
+            self.flags >>= 1;
+            if self.flags & FLAGS_MASK == 0 {
+                match self.next_byte()? {
+                    Some(flags) => self.flags = flags as u32 | FLAGS_BITS,
+                    None => return Ok(None),
+                }
+            }
+            Ok(Some(self.flags & 1 == 1))
+        }
+    }
+
+    impl<R: Read> Read for LzssReader<R> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let mut written = 0;
+            while written < buf.len() {
+                if self.match_remaining > 0 {
+                    let current = self.text_buf[self.match_position & N_MINUS1];
+                    self.match_position += 1;
+                    self.match_remaining -= 1;
+                    self.push(current);
+                    buf[written] = current;
+                    written += 1;
+                    continue;
+                }
+                if self.finished {
+                    break;
+                }
+                // The last flag byte usually has bits left over, so the data may end before any
+                // token.
+                let Some(literal) = self.next_flag()? else {
+                    self.finish()?;
+                    break;
+                };
+                if literal {
+                    let Some(current) = self.next_byte()? else {
+                        self.finish()?;
+                        break;
+                    };
+                    self.check_overshoot(1)?;
+                    self.push(current);
+                    buf[written] = current;
+                    written += 1;
+                } else {
+                    let Some(offset) = self.next_byte()? else {
+                        self.finish()?;
+                        break;
+                    };
+                    let Some(count) = self.next_byte()? else {
+                        if self.expected_size.is_some() {
+                            return Err(self.error("Data ends in the middle of a reference"));
+                        }
+                        self.finished = true;
+                        break;
+                    };
+                    let match_length = (count as u32 & COUNT_MASK) as usize + THRESHOLD + 1;
+                    self.check_overshoot(match_length)?;
+                    self.match_position =
+                        (offset as u32 | ((count as u32 & OFFSET_MASK) << 4)) as usize;
+                    self.match_remaining = match_length;
+                }
+            }
+            Ok(written)
+        }
+    }
+
     #[cfg(test)]
     mod tests {
         use super::*;
 
+        #[test]
+        fn test_compress_decompress_past_window() {
+            // Long enough to wrap the 4096 byte ring buffer several times.
+            let original_data = (0..20_000u32)
+                .map(|i| ((i * 7) % 251) as u8 ^ (i / 1000) as u8)
+                .chain(std::iter::repeat_n(b'A', 5000))
+                .collect::<Vec<u8>>();
+            let compressed_data = compress(&original_data);
+            assert!(compressed_data.len() < original_data.len());
+            assert_eq!(
+                decompress(&compressed_data, original_data.len()),
+                original_data
+            );
+        }
+
+        #[test]
+        fn test_lzss_reader_small_reads() {
+            let original_data = (0..10_000u32)
+                .map(|i| (i % 97) as u8 ^ (i / 500) as u8)
+                .collect::<Vec<u8>>();
+            let compressed_data = compress(&original_data);
+
+            // Pull a few bytes at a time so matches are split across reads.
+            let mut reader = LzssReader::new(&compressed_data[..]);
+            let mut decompressed_data = Vec::new();
+            let mut chunk = [0u8; 7];
+            loop {
+                match reader.read(&mut chunk).unwrap() {
+                    0 => break,
+                    read => decompressed_data.extend_from_slice(&chunk[..read]),
+                }
+            }
+            assert_eq!(decompressed_data, original_data);
+
+            let mut dst = vec![0u8; original_data.len() - 10];
+            assert_eq!(decompress_into(&compressed_data, &mut dst), dst.len());
+            assert_eq!(dst, &original_data[..dst.len()]);
+        }
+
+        #[test]
+        fn test_compression_levels() {
+            let original_data = (0..30_000u32)
+                .map(|i| ((i * 7) % 251) as u8 ^ (i / 1000) as u8)
+                .chain(std::iter::repeat_n(0, 3000))
+                .chain(b"Squall Rinoa Squall Rinoa Zell ".repeat(100))
+                .collect::<Vec<u8>>();
+            assert_eq!(
+                compress_with_level(&original_data, CompressionLevel::Exhaustive),
+                compress(&original_data)
+            );
+            for level in [
+                CompressionLevel::Fast,
+                CompressionLevel::Balanced,
+                CompressionLevel::Exhaustive,
+            ] {
+                let compressed_data = compress_with_level(&original_data, level);
+                assert!(
+                    compressed_data.len() < original_data.len() / 2,
+                    "{:?}",
+                    level
+                );
+                assert_eq!(
+                    decompress_strict(&compressed_data, original_data.len()).unwrap(),
+                    original_data
+                );
+                for short_data in [&b""[..], b"ab", b"abcabcabc"] {
+                    assert_eq!(
+                        decompress_strict(
+                            &compress_with_level(short_data, level),
+                            short_data.len()
+                        )
+                        .unwrap(),
+                        short_data
+                    );
+                }
+            }
+        }
+
+        #[test]
+        fn test_decompress_strict() {
+            let original_data = b"Lorem ipsum dolor sit amet, Lorem ipsum dolor".to_vec();
+            let compressed_data = compress(&original_data);
+            assert_eq!(
+                decompress_strict(&compressed_data, original_data.len()).unwrap(),
+                original_data
+            );
+
+            // The last token is the reference to "Lorem ipsum dolor".
+            let truncated = &compressed_data[..compressed_data.len() - 1];
+            let err = decompress_strict(truncated, original_data.len()).unwrap_err();
+            assert_eq!(err.position, truncated.len() as u64);
+            assert!(err.message.contains("middle of a reference"));
+
+            let err = decompress_strict(&compressed_data, original_data.len() - 1).unwrap_err();
+            assert!(err.message.contains("past the"));
+            let err = decompress_strict(&compressed_data, original_data.len() + 1).unwrap_err();
+            assert!(err.message.contains("ends after"));
+            assert_eq!(err.position, compressed_data.len() as u64);
+        }
+
         #[test]
         fn test_compress_decompress() {
             let _ = env_logger::builder().is_test(true).try_init();
@@ -1,37 +1,68 @@
 use std::{
-    collections::HashSet,
     io,
     ops::{Deref, DerefMut},
     path::{Path, PathBuf},
-    process::exit,
+    process::{exit, ExitCode},
     str::FromStr,
 };
 
 use oviiirs_archive::oviiirs_archive::*;
-mod lzss;
+use oviiirs_archive::{
+    default_cache_directory, detect_release, discover_install_directories, edit_install,
+    ArchiveCache, CacheLoad, CacheStatus, EntryEdit, ExtractionPlan, FileFilter, GameRelease,
+    OutputStatus,
+};
+mod cli;
+use clap::Parser;
+use cli::{Cli, Command};
 use lazy_static::lazy_static;
 use regex::Regex;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 
+// Set when config.toml could not be parsed and the menu runs with the defaults instead. The
+// broken file is backed up by the first save that overwrites it.
+static CONFIG_IS_BROKEN: AtomicBool = AtomicBool::new(false);
+
 lazy_static! {
     static ref CONFIG_PATH: String = "config.toml".to_string();
     static ref SHARED_CONFIG: Arc<Mutex<Config>> = {
-        match load_toml_from_file::<Config>(&CONFIG_PATH) {
-            Ok(mut config) => {
-                // Perform operations on the config before returning Arc<Mutex<Config>>
-                config.locations.ensure_chosen_directory_in_directories();
-
-                // Return the Arc<Mutex<Config>>
-                Arc::new(Mutex::new(config))
-            }
-            Err(_) => {
-                // Provide a default configuration here
+        match load_config() {
+            Ok(config) => Arc::new(Mutex::new(config)),
+            Err(err) => {
+                eprintln!("Could not load {}, using the defaults", err);
+                CONFIG_IS_BROKEN.store(true, Ordering::Relaxed);
                 Arc::new(Mutex::new(Config::default()))
             }
         }
     };
-    static ref TEXT_VIEW_MAP: Arc<Mutex<std::collections::HashMap<MainMenuSelection, String>>> = Arc::new(Mutex::new(
-    std::collections::HashMap::new()));
+    static ref TEXT_VIEW_MAP: Arc<Mutex<std::collections::HashMap<MainMenuSelection, String>>> =
+        Arc::new(Mutex::new(std::collections::HashMap::new()));
+}
+
+// Loads config.toml, or the defaults when there is none yet.
+fn load_config() -> io::Result<Config> {
+    match load_toml_from_file::<Config>(&CONFIG_PATH) {
+        Ok(mut config) => {
+            config.locations.ensure_chosen_directory_in_directories();
+            Ok(config)
+        }
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+        Err(err) => Err(err),
+    }
+}
+
+// Saves config.toml, keeping a copy of it first when it could not be loaded.
+fn save_config(config: &Config) -> io::Result<()> {
+    if CONFIG_IS_BROKEN.load(Ordering::Relaxed) {
+        let backup_path = backup_file(&CONFIG_PATH)?;
+        eprintln!(
+            "Saved a copy of the old {} to {}",
+            *CONFIG_PATH, backup_path
+        );
+        CONFIG_IS_BROKEN.store(false, Ordering::Relaxed);
+    }
+    save_toml(config, &CONFIG_PATH)
 }
 
 fn generate_main_menu_options() -> Vec<(MainMenuSelection, Option<String>, Option<String>)> {
@@ -63,10 +94,47 @@ fn generate_main_menu_options() -> Vec<(MainMenuSelection, Option<String>, Optio
         ),
         (MainMenuSelection::RebuildCache, None, None),
         (MainMenuSelection::Exit, None, None),
+        (MainMenuSelection::ReplaceFileInArchive, None, None),
+        (MainMenuSelection::ExtractChangedFiles, None, None),
     ]
 }
 
-fn main() -> io::Result<()> {
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match cli.command {
+        None | Some(Command::Menu) => match run_menu() {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(err) => {
+                eprintln!("Error: {}", err);
+                ExitCode::from(cli::EXIT_FAILURE)
+            }
+        },
+        Some(Command::Run(command)) => match load_config() {
+            Ok(config) => cli::run(command, &cli.overrides, config),
+            Err(err) => {
+                eprintln!("Error: {}", err);
+                ExitCode::from(cli::EXIT_FAILURE)
+            }
+        },
+    }
+}
+
+fn run_menu() -> io::Result<()> {
+    {
+        // Only the menu lists the installs Steam knows about, the other commands use the chosen
+        // directory and do not need to scan for them.
+        let mut config = SHARED_CONFIG.lock().unwrap();
+        let directories = config.locations.directories.clone();
+        let releases = config.locations.releases.clone();
+        config
+            .locations
+            .add_directories(discover_install_directories());
+        // Only the directories added since the last run are detected.
+        config.locations.detect_releases();
+        if config.locations.directories != directories || config.locations.releases != releases {
+            save_config(&config)?;
+        }
+    }
     let has_chosen_directory = {
         let config = SHARED_CONFIG.lock().unwrap();
         let path = Path::new(&config.locations.chosen_directory);
@@ -75,10 +143,9 @@ fn main() -> io::Result<()> {
     if !has_chosen_directory {
         let mut config = SHARED_CONFIG.lock().unwrap();
 
-        let config_path = &CONFIG_PATH;
         change_ff8_directory(&mut config);
 
-        save_toml(&*config, &config_path)?;
+        save_config(&config)?;
     }
 
     create_layout()?;
@@ -162,17 +229,12 @@ fn update_layout_text() {
 fn handle_button_click(label: &MainMenuSelection) -> io::Result<()> {
     let mut config = SHARED_CONFIG.lock().unwrap();
 
-    let config_path = &CONFIG_PATH;
-    let cache_path = "cache".generate_native_path();
-    let toml_path = cache_path.join("archives.toml").to_string();
-    let bincode_path = cache_path.join("archives.bin").to_string();
-    PathBuf::from(&toml_path).create_directories()?;
     // Perform actions based on the button click
     match label {
         MainMenuSelection::ChangeFF8Directory => {
             change_ff8_directory(&mut config);
 
-            save_toml(&*config, &config_path)?;
+            save_config(&config)?;
             update_layout_text();
         }
         MainMenuSelection::ChangeExtractDirectory => {
@@ -186,7 +248,7 @@ fn handle_button_click(label: &MainMenuSelection) -> io::Result<()> {
             match is_valid_path(&user_input_extract_path) {
                 true => {
                     config.locations.extract_directory = user_input_extract_path;
-                    save_toml(&*config, &config_path)?;
+                    save_config(&config)?;
                 }
                 false => {
                     eprintln!("Error not a valid path: \"{}\"\n", user_input_extract_path);
@@ -196,14 +258,22 @@ fn handle_button_click(label: &MainMenuSelection) -> io::Result<()> {
             update_layout_text();
         }
         MainMenuSelection::RebuildCache => {
-            let zzz_files = load_archives(&config)?;
-            save_toml(&zzz_files, &toml_path)?;
-            save_bincode(&zzz_files, &bincode_path)?;
+            rebuild_cache(&config)?;
         }
         MainMenuSelection::ExtractAllFiles => {
-            let zzz_files = load_or_rebuild_cache(&config, &toml_path, &bincode_path)?;
+            let zzz_files = load_or_rebuild_cache(&config)?;
+
+            extract_all_files(&zzz_files, &config, false)?;
+        }
+        MainMenuSelection::ReplaceFileInArchive => {
+            let zzz_files = load_or_rebuild_cache(&config)?;
+
+            replace_file_in_archive(&zzz_files)?;
+        }
+        MainMenuSelection::ExtractChangedFiles => {
+            let zzz_files = load_or_rebuild_cache(&config)?;
 
-            extract_all_files(&zzz_files, &config)?;
+            extract_all_files(&zzz_files, &config, true)?;
         }
         MainMenuSelection::ChangeRegExFilter => {
             println!("\nEnter a new extract RegEx filter: ");
@@ -216,11 +286,11 @@ fn handle_button_click(label: &MainMenuSelection) -> io::Result<()> {
 
             if user_input_regex_filter.is_empty() {
                 config.extract_regex_filter.clear();
-                save_toml(&*config, &config_path)?;
+                save_config(&config)?;
             } else if let Ok(_) = Regex::new(&user_input_regex_filter) {
                 // The regex is valid
                 config.extract_regex_filter = user_input_regex_filter;
-                save_toml(&*config, &config_path)?;
+                save_config(&config)?;
             } else {
                 eprintln!("Invalid RegEx r\"{}\"", user_input_regex_filter);
             }
@@ -237,29 +307,164 @@ fn handle_button_click(label: &MainMenuSelection) -> io::Result<()> {
     Ok(())
 }
 
-// Load data from bincode file if it exists, otherwise from TOML file or rebuild cache
-fn load_or_rebuild_cache(
-    config: &Config,
-    toml_path: &String,
-    bincode_path: &String,
-) -> Result<ZZZfiles, io::Error> {
-    match (
-        Path::new(bincode_path).exists(),
-        Path::new(toml_path).exists(),
-    ) {
-        (true, _) => load_bincode_from_file(bincode_path),
-        (false, true) => load_toml_from_file(toml_path),
-        (false, false) => {
-            let zzz_files = load_archives(config)?;
-
-            save_toml(&zzz_files, toml_path)?;
-            save_bincode(&zzz_files, bincode_path)?;
-
-            Ok(zzz_files)
+// Where the cache of the chosen install is kept. Without a user cache directory it goes to
+// cache/ in the working directory.
+fn cache_path(config: &Config) -> PathBuf {
+    let cache_directory = default_cache_directory().unwrap_or_else(|| PathBuf::from("cache"));
+    ArchiveCache::path_for_install(&cache_directory, &config.locations.chosen_directory)
+}
+
+// Scans the install and saves the result to the cache.
+fn rebuild_cache(config: &Config) -> io::Result<ZZZfiles> {
+    let zzz_files = load_archives(config)?;
+    save_cache(config, zzz_files)
+}
+
+fn save_cache(config: &Config, zzz_files: ZZZfiles) -> io::Result<ZZZfiles> {
+    let cache = ArchiveCache::new(&config.locations.chosen_directory, zzz_files)?;
+    cache.save(&cache_path(config))?;
+    Ok(cache.zzz_files)
+}
+
+// The cache of older versions, shared by every install.
+fn load_legacy_cache(config: &Config) -> io::Result<Option<ArchiveCache>> {
+    let legacy_path = "cache"
+        .generate_native_path()
+        .join("archives.bin")
+        .to_string();
+    if !Path::new(&legacy_path).is_file() {
+        return Ok(None);
+    }
+    ArchiveCache::load_legacy(Path::new(&legacy_path), &config.locations.chosen_directory)
+}
+
+// Loads the cache of the chosen install, or rebuilds it when there is none. A cache that no
+// longer matches the install is updated, only scanning the archives that changed.
+fn load_or_rebuild_cache(config: &Config) -> Result<ZZZfiles, io::Error> {
+    let (cache, imported) = match ArchiveCache::load(&cache_path(config))? {
+        CacheLoad::Loaded(cache) => (*cache, false),
+        CacheLoad::Missing => match load_legacy_cache(config)? {
+            Some(cache) => (cache, true),
+            None => return rebuild_cache(config),
+        },
+        CacheLoad::Discarded(reason) => {
+            eprintln!("Rebuilding the archive cache: {}", reason);
+            return rebuild_cache(config);
+        }
+    };
+    let install_directory = &config.locations.chosen_directory;
+    match cache.status(install_directory)? {
+        CacheStatus::Valid => {
+            if imported {
+                cache.save(&cache_path(config))?;
+            }
+            Ok(cache.zzz_files)
+        }
+        CacheStatus::Invalid(reason) => {
+            eprintln!("Rebuilding the archive cache: {}", reason);
+            rebuild_cache(config)
+        }
+        CacheStatus::Stale { zzz_paths, loose } => {
+            let mut zzz_files = cache.zzz_files;
+            for path in &zzz_paths {
+                eprintln!("Updating the archive cache for {}", path);
+                if Path::new(path).is_file() {
+                    zzz_files.push(load_zzz_archive(path)?);
+                } else {
+                    for slot in [&mut zzz_files.main, &mut zzz_files.other] {
+                        if slot.as_ref().is_some_and(|zzz| &zzz.file_path == path) {
+                            *slot = None;
+                        }
+                    }
+                }
+            }
+            if loose {
+                eprintln!("Updating the archive cache for the loose archives");
+                zzz_files.loose = load_loose_archives(install_directory)?;
+            }
+            save_cache(config, zzz_files)
         }
     }
 }
 
+fn read_trimmed_line(prompt: &str) -> String {
+    println!("\n{}", prompt);
+    let mut user_input = String::new();
+    io::stdin()
+        .read_line(&mut user_input)
+        .expect("Failed to read user input");
+    user_input.trim().to_string()
+}
+
+fn replace_file_in_archive(zzz_files: &ZZZfiles) -> io::Result<()> {
+    let fl_path = read_trimmed_line("Enter the path of the file inside the archive: ");
+    if fl_path.is_empty() {
+        eprintln!("Error no path entered\n");
+        return Ok(());
+    }
+    let source_path =
+        read_trimmed_line("Enter the path of the new file (leave empty to remove the entry): ");
+    let output_directory =
+        read_trimmed_line("Enter the directory to write the rebuilt archive to: ");
+    if !Path::new(&output_directory).is_dir() {
+        eprintln!("Error not a directory: \"{}\"\n", output_directory);
+        return Ok(());
+    }
+
+    let mut edit = if source_path.is_empty() {
+        EntryEdit::Remove
+    } else {
+        EntryEdit::Replace {
+            data: std::fs::read(&source_path)?,
+            compression_type: None,
+        }
+    };
+
+    loop {
+        match edit_install(zzz_files, &fl_path, &edit, Path::new(&output_directory)) {
+            Ok(Some(written)) => {
+                for path in written {
+                    println!("Wrote {}", path.display());
+                }
+                return Ok(());
+            }
+            Ok(None) => {}
+            Err(err) => {
+                eprintln!("Error {}\n", err);
+                return Ok(());
+            }
+        }
+
+        edit = match edit {
+            // Not in an archive yet, so offer to add it instead.
+            EntryEdit::Replace { data, .. } => {
+                let archive = read_trimmed_line(
+                    "File not found. Enter the fs path of the archive to add it to: ",
+                );
+                let compression_type =
+                    match read_trimmed_line("Enter the compression (none, lzss, lz4): ")
+                        .parse::<CompressionTypeT>()
+                    {
+                        Ok(compression_type) => compression_type,
+                        Err(err) => {
+                            eprintln!("Error {}\n", err);
+                            return Ok(());
+                        }
+                    };
+                EntryEdit::Add {
+                    archive,
+                    data,
+                    compression_type,
+                }
+            }
+            _ => {
+                eprintln!("Error \"{}\" was not found in any archive\n", fl_path);
+                return Ok(());
+            }
+        };
+    }
+}
+
 fn is_valid_path(path_str: &str) -> bool {
     let path_buf = PathBuf::from(path_str);
 
@@ -275,7 +480,11 @@ fn is_valid_path(path_str: &str) -> bool {
 
 fn change_ff8_directory(config: &mut Config) {
     let directories = filter_valid_directories(&config.locations.directories);
-    let user_choice = display_directory_info(&directories, &config.locations.chosen_directory);
+    let user_choice = display_directory_info(
+        &directories,
+        &config.locations.releases,
+        &config.locations.chosen_directory,
+    );
     // display_directory_info();
 
     config.locations.chosen_directory = match user_choice {
@@ -305,127 +514,116 @@ fn change_ff8_directory(config: &mut Config) {
             exit(0);
         }
     };
+    // The directory may hold another release than when it was first seen.
+    let chosen_directory = config.locations.chosen_directory.clone();
+    config.locations.detect_release(&chosen_directory);
 }
 
-fn extract_all_files(zzz_files: &ZZZfiles, config: &Config) -> io::Result<()> {
-    let re = match &config.extract_regex_filter {
-        s if s.is_empty() => Regex::new(r".*"),
-        _ => Regex::new(&config.extract_regex_filter),
-    };
-    let archive_strings = get_archive_strings(&zzz_files.main);
-    zzz_files
-        .into_iter()
-        .try_for_each(|opt_zzz_file| -> io::Result<()> {
-            if let Some(zzz_file) = opt_zzz_file {
-                let filtered_entries = zzz_file.entries.iter().filter(|&entry| {
-                    !archive_strings.contains(&entry.string_data)
-                        && (re.is_err()
-                            || re.as_ref().is_ok_and(|r| r.is_match(&entry.string_data)))
-                });
-                extract_zzz_files(filtered_entries, &zzz_file.file_path, &config)?;
-
-                if let Some(archives) = zzz_file.fiflfs_files.as_ref() {
-                    extract_archives(
-                        archives
-                            .iter()
-                            .filter(|&item| item.archive_type != ArchiveType::Field),
-                        &config,
-                    )?;
-
-                    if let Some(field) = archives
-                        .iter()
-                        .find(|&item| item.archive_type == ArchiveType::Field)
-                    {
-                        if let Some(field_archives) = field.field_archives.as_ref() {
-                            extract_archives(field_archives.iter(), &config)?;
-                        }
-                    }
-                }
-            }
-            Ok(())
+fn extract_all_files(zzz_files: &ZZZfiles, config: &Config, incremental: bool) -> io::Result<()> {
+    let filter = FileFilter::from_config(config)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+    let plan = ExtractionPlan::new(zzz_files, &filter, &config.locations.extract_directory);
+    if !incremental {
+        plan.extract(|planned| {
+            println!(
+                "{} ({} bytes)",
+                planned.destination.display(),
+                planned.file.uncompressed_size()
+            )
         })?;
-    Ok(())
-}
-
-fn get_archive_strings(archive: &Option<ZZZHeader>) -> HashSet<String> {
-    let mut archive_strings = HashSet::new();
-    if let Some(main) = archive.as_ref() {
-        if let Some(archives) = main.fiflfs_files.as_ref() {
-            for archive in archives {
-                archive_strings.insert(archive.fi.string_data.clone());
-                archive_strings.insert(archive.fs.string_data.clone());
-                archive_strings.insert(archive.fl.string_data.clone());
-            }
-        }
+        return Ok(());
     }
-    archive_strings
+    let report = plan.extract_incremental(|planned, status| match status {
+        OutputStatus::Unchanged => println!("{} is up to date", planned.destination.display()),
+        status => println!(
+            "{} ({} bytes, {:?})",
+            planned.destination.display(),
+            planned.file.uncompressed_size(),
+            status
+        ),
+    })?;
+    println!(
+        "Wrote {} files ({} bytes), skipped {} that were up to date",
+        report.written(),
+        report.bytes_written,
+        report.skipped()
+    );
+    Ok(())
 }
 
 fn load_archives(config: &Config) -> io::Result<ZZZfiles> {
+    let directory = &config.locations.chosen_directory;
+    // The release saved when the directory was chosen, detected now for --install-dir.
+    let release = match config.locations.releases.get(directory) {
+        Some(info) => info.release,
+        None => detect_release(directory)?.release,
+    };
     let mut zzz_files: ZZZfiles = Default::default();
-    let zzz_paths = process_files_in_directory(&config.locations.chosen_directory)?;
 
-    zzz_paths.iter().try_for_each(|path| -> io::Result<()> {
-        let mut data = read_data_from_file(&path)?;
+    if matches!(release, GameRelease::Remastered | GameRelease::Unknown) {
+        let zzz_paths = process_files_in_directory(directory)?;
 
-        if data.fiflfs_files.is_none() || data.fiflfs_files.as_ref().unwrap().is_empty() {
-            data.fiflfs_files
-                .get_or_insert_with(|| find_archives(data.entries.clone(), &path));
+        zzz_paths.iter().try_for_each(|path| -> io::Result<()> {
+            zzz_files.push(load_zzz_archive(path)?);
+            Ok(())
+        })?;
+    }
 
-            if let Some(archives) = data.fiflfs_files.as_mut() {
-                load_archives_fi_fl(archives.iter_mut())?;
+    if release != GameRelease::Remastered {
+        zzz_files.loose = load_loose_archives(directory)?;
+    }
+    Ok(zzz_files)
+}
 
-                if let Some(field) = archives
-                    .iter_mut()
-                    .find(|item| item.archive_type == ArchiveType::Field)
-                {
-                    if field.field_archives.is_none()
-                        || field.field_archives.as_ref().unwrap().is_empty()
-                    {
-                        field.field_archives = Some(
-                            field
-                                .field_archives
-                                .take()
-                                .map_or_else(|| find_archives_field(field), Ok)?,
-                        );
-                    }
+fn load_zzz_archive(path: &String) -> io::Result<ZZZHeader> {
+    let mut data = read_data_from_file(path)?;
 
-                    if let Some(field_archives) = field.field_archives.as_mut() {
-                        load_archives_fi_fl(field_archives.iter_mut())?;
-                    }
-                }
-            }
-        }
+    if data.fiflfs_files.is_none() || data.fiflfs_files.as_ref().unwrap().is_empty() {
+        data.fiflfs_files
+            .get_or_insert_with(|| find_archives(data.entries.clone(), path));
 
-        zzz_files.push(data);
-        Ok(())
-    })?;
-    Ok(zzz_files)
+        load_fiflfs_files(&mut data)?;
+    }
+    Ok(data)
 }
 
-fn extract_zzz_files<'a, I>(entries: I, file_path: &String, config: &Config) -> io::Result<()>
-where
-    I: Iterator<Item = &'a ZZZEntry>,
-{
-    for entry in entries {
-        let native_file_path = &entry
-            .string_data
-            .generate_relative_path_from_windows_path_string();
-        let extract_path = &config.locations.extract_directory.generate_native_path();
-        let new_extract_path = PathBuf::from(extract_path.join(native_file_path).as_str());
-        new_extract_path.create_directories()?;
-
-        let decompressed_bytes =
-            read_bytes_from_file(file_path, entry.file_offset, entry.file_size as u64)?;
-
-        println!(
-            "file offset: {}, file size {}, relative path {}",
-            entry.file_offset,
-            entry.file_size,
-            new_extract_path.display()
-        );
-        println!("--------------------------");
-        write_bytes_to_file(&new_extract_path, &decompressed_bytes)?;
+// The 2000 and Steam 2013 releases keep their fi/fl/fs as loose files instead.
+fn load_loose_archives(directory: &String) -> io::Result<Option<ZZZHeader>> {
+    let loose_archives = find_loose_archives(directory)?;
+    if loose_archives.is_empty() {
+        return Ok(None);
+    }
+    let mut data = ZZZHeader {
+        file_path: directory.clone(),
+        fiflfs_files: Some(loose_archives),
+        ..Default::default()
+    };
+    load_fiflfs_files(&mut data)?;
+    Ok(Some(data))
+}
+
+fn load_fiflfs_files(data: &mut ZZZHeader) -> io::Result<()> {
+    if let Some(archives) = data.fiflfs_files.as_mut() {
+        load_archives_fi_fl(archives.iter_mut())?;
+
+        // Steam 2013 has a field archive per language.
+        for field in archives
+            .iter_mut()
+            .filter(|item| item.archive_type == ArchiveType::Field)
+        {
+            if field.field_archives.is_none() || field.field_archives.as_ref().unwrap().is_empty() {
+                field.field_archives = Some(
+                    field
+                        .field_archives
+                        .take()
+                        .map_or_else(|| find_archives_field(field), Ok)?,
+                );
+            }
+
+            if let Some(field_archives) = field.field_archives.as_mut() {
+                load_archives_fi_fl(field_archives.iter_mut())?;
+            }
+        }
     }
     Ok(())
 }
@@ -438,14 +636,14 @@ where
         // Now you can use `archive` as a mutable reference
         if archive.fi_file.is_none() || archive.fi_file.as_ref().unwrap().entries.is_empty() {
             archive.fi_file = Some(archive.fi_file.take().map_or_else(
-                || FIfile::from_zzz_entry_and_file(&archive.fi, &archive.file_path),
+                || FIfile::from_zzz_entry_and_file(&archive.fi, archive.fi_file_path()),
                 Ok,
             )?);
         }
 
         if archive.fl_file.is_none() || archive.fi_file.as_ref().unwrap().entries.is_empty() {
             archive.fl_file = Some(archive.fl_file.take().map_or_else(
-                || FLfile::from_zzz_entry_and_file(&archive.fl, &archive.file_path),
+                || FLfile::from_zzz_entry_and_file(&archive.fl, archive.fl_file_path()),
                 Ok,
             )?);
         }
@@ -453,97 +651,3 @@ where
 
     Ok(())
 }
-
-fn extract_archives<'a, I>(archives: I, config: &Config) -> io::Result<()>
-where
-    I: Iterator<Item = &'a FIFLFSZZZ>,
-{
-    let re = match &config.extract_regex_filter {
-        s if s.is_empty() => Regex::new(r".*"),
-        _ => Regex::new(&config.extract_regex_filter),
-    };
-    for archive in archives {
-        if archive.fi_file.is_none() || archive.fi_file.is_none() {
-            continue;
-        }
-        //let fi_file = read_fi_entries_from_file(&archive.fi, &archive.file_path)?;
-
-        //let fl_file = read_fl_entries_from_file(&archive.fl, &archive.file_path)?;
-
-        let fi_file = archive.fi_file.as_ref().unwrap();
-        let fl_file = archive.fl_file.as_ref().unwrap();
-
-        // Technically you don't need to always read the whole fs into memory except when it or it's parents are compressed. Just a simplication to load it into memory.
-        let fs_bytes = match archive.fs.compression_type {
-            CompressionTypeT::None => read_bytes_from_file(
-                &archive.file_path,
-                archive.fs.file_offset,
-                archive.fs.file_size as u64,
-            )?,
-            CompressionTypeT::Lzss => lzss::decompress(
-                &read_compressed_bytes_from_file_at_offset_lzss(
-                    &archive.file_path,
-                    archive.fs.file_offset,
-                )?,
-                archive.fs.file_size as usize,
-            ),
-            CompressionTypeT::Lz4 => lz4_decompress(
-                &read_compressed_bytes_from_file_at_offset_lz4(
-                    &archive.file_path,
-                    archive.fs.file_offset,
-                )?,
-                archive.fs.file_size as usize,
-            )?,
-        };
-
-        for fi_fl in fi_file
-            .entries
-            .iter()
-            .zip(fl_file.entries.iter())
-            .filter(|(fi, fl)| {
-                fi.uncompressed_size != 0
-                    && (re.is_err() || re.as_ref().is_ok_and(|r| r.is_match(&fl)))
-            })
-        {
-            let (fi, fl) = fi_fl;
-
-            let native_file_path = &fl.generate_relative_path_from_windows_path_string();
-            let extract_path = &config.locations.extract_directory.generate_native_path();
-            let new_extract_path = PathBuf::from(extract_path.join(native_file_path).as_str());
-            new_extract_path.create_directories()?;
-
-            println!("FI: {:?}", fi);
-            println!("FL: {:?}", fl);
-            println!(
-                "file offset: {}, file size {}, relative path {}",
-                fi.offset,
-                fi.uncompressed_size,
-                new_extract_path.display()
-            );
-            println!("--------------------------");
-
-            let uncompressed_bytes = match fi.compression_type {
-                CompressionTypeT::None => read_bytes_from_memory(
-                    &fs_bytes,
-                    fi.offset as usize,
-                    fi.uncompressed_size as usize,
-                ),
-                CompressionTypeT::Lzss => lzss::decompress(
-                    &read_compressed_bytes_from_memory_at_offset_lzss(
-                        &fs_bytes,
-                        fi.offset as usize,
-                    ),
-                    fi.uncompressed_size as usize,
-                ),
-                CompressionTypeT::Lz4 => lz4_decompress(
-                    &read_compressed_bytes_from_memory_at_offset_lz4(&fs_bytes, fi.offset as usize),
-                    fi.uncompressed_size as usize,
-                )?,
-            };
-
-            write_bytes_to_file(&new_extract_path, &uncompressed_bytes)?;
-        }
-    }
-    //end dump toml of data
-    Ok(())
-}
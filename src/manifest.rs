@@ -0,0 +1,140 @@
+use crate::checksum::{file_crc32, ChecksumWriter};
+use crate::error::ArchiveResult;
+use crate::extract::FileCopier;
+use crate::oviiirs_archive::{load_toml_from_file, save_toml, CompressionTypeT};
+use crate::vfs::{ArchiveFile, ArchiveFileSource};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+/// Name of the manifest an incremental extraction keeps in the extract directory.
+pub const MANIFEST_FILE_NAME: &str = "oviiirs_manifest.toml";
+
+/// What an incremental extraction wrote, so the next one can skip the outputs that are still
+/// up to date.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ExtractManifest {
+    /// Keyed by the output path relative to the extract directory, with `/` separators.
+    #[serde(default)]
+    pub files: BTreeMap<String, ManifestEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub source: SourceFingerprint,
+    pub size: u64,
+    /// Modification time of the output in nanoseconds since the Unix epoch. While it matches,
+    /// the output is not hashed again.
+    pub modified: u64,
+    pub crc32: u32,
+}
+
+/// Identifies the data of an entry without decompressing it: where it is stored and a CRC32 of
+/// its stored bytes. Other entries of the same archive changing leaves it as it is.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SourceFingerprint {
+    pub archive_path: String,
+    pub offset: u64,
+    pub uncompressed_size: u64,
+    pub compression_type: CompressionTypeT,
+    /// Bytes the entry takes up in its archive, with the size header of compressed entries.
+    #[serde(default)]
+    pub stored_size: u64,
+    #[serde(default)]
+    pub stored_crc32: u32,
+}
+
+/// Why an incremental extraction writes an output, or `Unchanged` when it skips it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputStatus {
+    Unchanged,
+    /// The manifest has no record of the output.
+    New,
+    SourceChanged,
+    OutputModified,
+    OutputMissing,
+}
+
+impl ExtractManifest {
+    /// Loads the manifest of `extract_directory`, an empty one if there is none yet.
+    pub fn load(extract_directory: &Path) -> io::Result<Self> {
+        let path = extract_directory.join(MANIFEST_FILE_NAME);
+        if !path.exists() {
+            return Ok(ExtractManifest::default());
+        }
+        load_toml_from_file(&path.to_string_lossy().into_owned())
+    }
+
+    pub fn save(&self, extract_directory: &Path) -> io::Result<()> {
+        fs::create_dir_all(extract_directory)?;
+        save_toml(
+            self,
+            &extract_directory.join(MANIFEST_FILE_NAME).to_string_lossy(),
+        )
+    }
+
+    /// Compares the output at `output_path` and its `source` with what was recorded for `key`.
+    pub fn status(
+        &self,
+        key: &str,
+        source: &SourceFingerprint,
+        output_path: &Path,
+    ) -> io::Result<OutputStatus> {
+        let Some(entry) = self.files.get(key) else {
+            return Ok(OutputStatus::New);
+        };
+        if entry.source != *source {
+            return Ok(OutputStatus::SourceChanged);
+        }
+        let metadata = match fs::metadata(output_path) {
+            Ok(metadata) => metadata,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                return Ok(OutputStatus::OutputMissing)
+            }
+            Err(err) => return Err(err),
+        };
+        if metadata.len() != entry.size {
+            return Ok(OutputStatus::OutputModified);
+        }
+        if modified_nanos(&metadata) == entry.modified || file_crc32(output_path)? == entry.crc32 {
+            return Ok(OutputStatus::Unchanged);
+        }
+        Ok(OutputStatus::OutputModified)
+    }
+}
+
+impl SourceFingerprint {
+    /// Reads the stored bytes of `file` through `copier`, which keeps its archive open for
+    /// extracting it afterwards.
+    pub fn new<'a>(file: &ArchiveFile<'a>, copier: &mut FileCopier<'a>) -> ArchiveResult<Self> {
+        let mut writer = ChecksumWriter::crc32_only(io::sink());
+        copier.copy_stored_file(file, &mut writer)?;
+        let stored = writer.finish();
+        let (archive_path, offset) = match file.source {
+            ArchiveFileSource::ZZZ { zzz, entry } => (zzz.file_path.clone(), entry.file_offset),
+            ArchiveFileSource::FIFLFS { archive, fi, .. } => (
+                archive.fs_file_path().to_string(),
+                archive.fs.file_offset + fi.offset as u64,
+            ),
+        };
+        Ok(SourceFingerprint {
+            archive_path,
+            offset,
+            uncompressed_size: file.uncompressed_size(),
+            compression_type: file.compression_type(),
+            stored_size: stored.size,
+            stored_crc32: stored.crc32,
+        })
+    }
+}
+
+pub(crate) fn modified_nanos(metadata: &fs::Metadata) -> u64 {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |duration| duration.as_nanos() as u64)
+}
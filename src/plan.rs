@@ -0,0 +1,343 @@
+use crate::checksum::ChecksumWriter;
+use crate::error::{ArchiveResult, ResultExt};
+use crate::extract::{write_output, FileCopier};
+use crate::manifest::{
+    modified_nanos, ExtractManifest, ManifestEntry, OutputStatus, SourceFingerprint,
+};
+use crate::oviiirs_archive::{
+    ArchiveType, GenerateNativePath, GenerateRelativePathFromWindowsPathString, ZZZfiles,
+};
+use crate::query::FileFilter;
+use crate::vfs::{archive_files, ArchiveFile, ArchiveFileSource};
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
+
+/// A file an extraction writes and where it goes.
+#[derive(Debug, Clone)]
+pub struct PlannedFile<'a> {
+    pub file: ArchiveFile<'a>,
+    pub destination: PathBuf,
+}
+
+/// Files of the plan that go to the same destination, so all but the last are overwritten.
+/// Destinations are compared without regard to case, the way the game's paths are.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlanConflict {
+    pub destination: PathBuf,
+    /// Indices into [`ExtractionPlan::files`], in the order they would be written.
+    pub files: Vec<usize>,
+}
+
+/// What [`ExtractionPlan::extract_incremental`] did.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IncrementalReport {
+    /// One for each file of the plan, in the same order.
+    pub statuses: Vec<OutputStatus>,
+    pub bytes_written: u64,
+}
+
+impl IncrementalReport {
+    pub fn skipped(&self) -> usize {
+        self.statuses
+            .iter()
+            .filter(|&&status| status == OutputStatus::Unchanged)
+            .count()
+    }
+
+    pub fn written(&self) -> usize {
+        self.statuses.len() - self.skipped()
+    }
+}
+
+/// Everything an extraction would write, worked out without touching the output directory.
+#[derive(Debug, Clone, Default)]
+pub struct ExtractionPlan<'a> {
+    pub extract_directory: PathBuf,
+    pub files: Vec<PlannedFile<'a>>,
+}
+
+impl<'a> ExtractionPlan<'a> {
+    /// Plans the extraction of the files `filter` matches into `extract_directory`. The fi, fl
+    /// and fs entries of a `.zzz` are left out since their files are extracted instead, as is
+    /// field, whose files are the archives of each map.
+    pub fn new(zzz_files: &'a ZZZfiles, filter: &FileFilter, extract_directory: &str) -> Self {
+        let extract_directory = PathBuf::from(extract_directory.generate_native_path().as_str());
+        let mut archive_entries = HashSet::new();
+        for zzz in zzz_files.into_iter().flatten() {
+            for archive in zzz.fiflfs_files.iter().flatten() {
+                archive_entries.extend([
+                    archive.fi.string_data.as_str(),
+                    archive.fl.string_data.as_str(),
+                    archive.fs.string_data.as_str(),
+                ]);
+            }
+        }
+        let files = archive_files(zzz_files)
+            .into_iter()
+            .filter(|file| match file.source {
+                ArchiveFileSource::ZZZ { entry, .. } => {
+                    !archive_entries.contains(entry.string_data.as_str())
+                }
+                ArchiveFileSource::FIFLFS { archive, .. } => {
+                    archive.archive_type != ArchiveType::Field
+                }
+            })
+            .filter(|file| filter.matches(file))
+            .map(|file| PlannedFile {
+                destination: extract_directory.join(
+                    file.path
+                        .generate_relative_path_from_windows_path_string()
+                        .as_str(),
+                ),
+                file,
+            })
+            .collect();
+        ExtractionPlan {
+            extract_directory,
+            files,
+        }
+    }
+
+    /// Bytes written by the extraction, ignoring files that overwrite each other.
+    pub fn total_size(&self) -> u64 {
+        self.files
+            .iter()
+            .map(|planned| planned.file.uncompressed_size())
+            .sum()
+    }
+
+    pub fn conflicts(&self) -> Vec<PlanConflict> {
+        let mut by_destination: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+        for (index, planned) in self.files.iter().enumerate() {
+            by_destination
+                .entry(planned.destination.to_string_lossy().to_lowercase())
+                .or_default()
+                .push(index);
+        }
+        by_destination
+            .into_values()
+            .filter(|files| files.len() > 1)
+            .map(|files| PlanConflict {
+                destination: self.files[files[0]].destination.clone(),
+                files,
+            })
+            .collect()
+    }
+
+    /// Writes every file of the plan, calling `progress` before each one. Returns the number of
+    /// bytes written.
+    pub fn extract<F>(&self, mut progress: F) -> ArchiveResult<u64>
+    where
+        F: FnMut(&PlannedFile<'a>),
+    {
+        let mut copier = FileCopier::new();
+        let mut written = 0;
+        for planned in &self.files {
+            progress(planned);
+            written += copier.extract_file(&planned.file, &planned.destination)?;
+        }
+        Ok(written)
+    }
+
+    /// Like [`extract`](Self::extract), but skips the outputs that the manifest in the extract
+    /// directory shows are up to date, and records the ones written there. `progress` is called
+    /// with each file and what is done with it.
+    pub fn extract_incremental<F>(&self, mut progress: F) -> ArchiveResult<IncrementalReport>
+    where
+        F: FnMut(&PlannedFile<'a>, OutputStatus),
+    {
+        let manifest_directory = self.extract_directory.to_string_lossy();
+        let mut manifest =
+            ExtractManifest::load(&self.extract_directory).with_path(&manifest_directory)?;
+        let mut report = IncrementalReport::default();
+        let mut copier = FileCopier::new();
+        let result = self
+            .files
+            .iter()
+            .try_for_each(|planned| -> ArchiveResult<()> {
+                let source = SourceFingerprint::new(&planned.file, &mut copier)?;
+                let destination = planned.destination.to_string_lossy();
+                let key = self.relative_path(planned);
+                let status = manifest
+                    .status(&key, &source, &planned.destination)
+                    .with_path(&destination)?;
+                progress(planned, status);
+                report.statuses.push(status);
+                if status == OutputStatus::Unchanged {
+                    return Ok(());
+                }
+
+                let mut crc32 = 0;
+                let size = write_output(&planned.destination, |writer| {
+                    let mut checksum_writer = ChecksumWriter::crc32_only(writer);
+                    let size = copier.copy_file(&planned.file, &mut checksum_writer)?;
+                    crc32 = checksum_writer.finish().crc32;
+                    Ok(size)
+                })?;
+                let modified =
+                    modified_nanos(&fs::metadata(&planned.destination).with_path(&destination)?);
+                manifest.files.insert(
+                    key,
+                    ManifestEntry {
+                        source,
+                        size,
+                        modified,
+                        crc32,
+                    },
+                );
+                report.bytes_written += size;
+                Ok(())
+            });
+        // What was written before a failure is recorded too, so it is not written again.
+        manifest
+            .save(&self.extract_directory)
+            .with_path(&manifest_directory)?;
+        result.map(|()| report)
+    }
+
+    /// The destination relative to the extract directory with `/` separators, the key of the
+    /// file in the manifests.
+    pub(crate) fn relative_path(&self, planned: &PlannedFile) -> String {
+        planned
+            .destination
+            .strip_prefix(&self.extract_directory)
+            .unwrap_or(&planned.destination)
+            .to_string_lossy()
+            .replace('\\', "/")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fiflfs_writer::FIFLFSWriter;
+    use crate::oviiirs_archive::CompressionTypeT;
+    use crate::query::Query;
+    use crate::test_util::{load_zzz, TempDir};
+    use crate::zzz_writer::ZZZWriter;
+    use std::fs;
+    use std::io::Write;
+    use std::path::Path;
+
+    // A main.zzz with a loose file and a menu archive, one of whose files has the same output
+    // path as the loose one.
+    fn write_test_install(directory: &Path) -> ZZZfiles {
+        let zzz_path = directory.join("main.zzz").to_str().unwrap().to_string();
+        let mut menu_writer = FIFLFSWriter::new();
+        menu_writer
+            .add_bytes(
+                "c:\\ff8\\data\\eng\\menu\\Mngrp.bin",
+                b"menu groups".repeat(20),
+                CompressionTypeT::Lzss,
+            )
+            .unwrap();
+        menu_writer
+            .add_bytes(
+                "c:\\ff8\\data\\eng\\menu\\face1.tex",
+                b"faces".to_vec(),
+                CompressionTypeT::None,
+            )
+            .unwrap();
+        let mut zzz_writer = ZZZWriter::new();
+        zzz_writer
+            .add_bytes("ff8\\data\\eng\\menu\\FACE1.TEX", b"other faces".to_vec())
+            .unwrap();
+        menu_writer
+            .add_to_zzz(&mut zzz_writer, "data\\lang-en\\menu")
+            .unwrap();
+        zzz_writer.write_to_file(&zzz_path).unwrap();
+
+        let mut zzz_files = ZZZfiles::default();
+        assert!(zzz_files.push(load_zzz(&zzz_path)));
+        zzz_files
+    }
+
+    #[test]
+    fn test_extraction_plan() {
+        let directory = TempDir::new("plan");
+        let zzz_files = write_test_install(&directory);
+        let output = directory.join("out");
+
+        let plan = ExtractionPlan::new(&zzz_files, &FileFilter::new(), output.to_str().unwrap());
+        // The menu fi/fl/fs entries of the zzz are not extracted themselves.
+        assert_eq!(plan.files.len(), 3);
+        assert_eq!(plan.total_size(), 11 + 220 + 5);
+        let conflicts = plan.conflicts();
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].files.len(), 2);
+        assert!(conflicts[0].files.iter().all(|&index| plan.files[index]
+            .file
+            .path
+            .to_lowercase()
+            .ends_with("face1.tex")));
+        // Planning writes nothing.
+        assert!(!output.exists());
+
+        let filter = FileFilter::new().include(Query::parse("*.bin").unwrap());
+        let plan = ExtractionPlan::new(&zzz_files, &filter, output.to_str().unwrap());
+        assert!(plan.conflicts().is_empty());
+        let mut progress = Vec::new();
+        assert_eq!(
+            plan.extract(|planned| progress.push(planned.destination.clone()))
+                .unwrap(),
+            220
+        );
+        assert_eq!(progress, [output.join("ff8/data/eng/menu/Mngrp.bin")]);
+        assert_eq!(fs::read(&progress[0]).unwrap(), b"menu groups".repeat(20));
+    }
+
+    #[test]
+    fn test_incremental_extraction() {
+        let directory = TempDir::new("incremental");
+        let zzz_files = write_test_install(&directory);
+        let output = directory.join("out");
+        let filter = FileFilter::new().exclude(Query::parse("face1.tex").unwrap());
+        let plan = ExtractionPlan::new(&zzz_files, &filter, output.to_str().unwrap());
+        assert_eq!(plan.files.len(), 1);
+        let extract = || plan.extract_incremental(|_, _| {}).unwrap();
+
+        let report = extract();
+        assert_eq!(report.statuses, [OutputStatus::New]);
+        assert_eq!(report.bytes_written, 220);
+        let report = extract();
+        assert_eq!((report.skipped(), report.bytes_written), (1, 0));
+
+        let output_path = &plan.files[0].destination;
+        fs::write(output_path, b"modded menu groups").unwrap();
+        assert_eq!(extract().statuses, [OutputStatus::OutputModified]);
+        assert_eq!(fs::read(output_path).unwrap(), b"menu groups".repeat(20));
+        fs::remove_file(output_path).unwrap();
+        assert_eq!(extract().statuses, [OutputStatus::OutputMissing]);
+
+        // Touching the output without changing it is caught by the checksum.
+        let file = fs::File::options().append(true).open(output_path).unwrap();
+        file.set_modified(std::time::SystemTime::UNIX_EPOCH)
+            .unwrap();
+        assert_eq!(extract().statuses, [OutputStatus::Unchanged]);
+        // Only the bytes of the entry itself count, not the rest of its archive.
+        fs::File::options()
+            .append(true)
+            .open(directory.join("main.zzz"))
+            .unwrap()
+            .write_all(b"padding")
+            .unwrap();
+        assert_eq!(extract().statuses, [OutputStatus::Unchanged]);
+
+        let mut manifest = ExtractManifest::load(&output).unwrap();
+        manifest
+            .files
+            .values_mut()
+            .for_each(|entry| entry.source.stored_crc32 ^= 1);
+        manifest.save(&output).unwrap();
+        assert_eq!(extract().statuses, [OutputStatus::SourceChanged]);
+        assert_eq!(
+            ExtractManifest::load(&output)
+                .unwrap()
+                .files
+                .keys()
+                .collect::<Vec<_>>(),
+            ["ff8/data/eng/menu/Mngrp.bin"]
+        );
+    }
+}
@@ -0,0 +1,577 @@
+use crate::oviiirs_archive::{ArchiveType, CompressionTypeT, Config, LanguageCode};
+use crate::vfs::{normalize_archive_path, ArchiveFile};
+use regex::Regex;
+use std::fmt;
+
+/// A predicate over the files of the install, parsed from text like
+/// `lang:fr archive:menu (path:*.tex or path:*.tim) not size>1MB` or built with the constructors.
+///
+/// Terms are `archive:<type>`, `lang:<code>`, `compression:<none|lzss|lz4>`, `path:<glob>`,
+/// `re:<regex>` and `size` compared with `<`, `<=`, `=`, `>=` or `>` to a byte count with an
+/// optional `KB`, `MB` or `GB` suffix (powers of 1024). A term without a key is a glob. Terms
+/// next to each other must all match, `and`, `or`, `not` and parentheses combine them as
+/// usual. Values with spaces or parentheses are quoted: `re:"(a|b)\.msd$"`.
+///
+/// Globs are matched without regard to case or separator style, `*` and `?` stay inside one
+/// directory and `**` crosses them. A glob without a separator is matched against the file
+/// name only. Regexes are matched against the path as it is spelled in the archive, like
+/// `extract_regex_filter`.
+#[derive(Debug, Clone)]
+pub enum Query {
+    /// The type of the outermost fi/fl/fs archive, lowercase.
+    Archive(String),
+    /// The language of the file's archive, lowercase.
+    Language(String),
+    Compression(CompressionTypeT),
+    /// The uncompressed size compared with a number of bytes.
+    Size(SizeComparison, u64),
+    /// A glob already turned into a regex over the normalized path.
+    Glob(Regex),
+    Regex(Regex),
+    Not(Box<Query>),
+    And(Vec<Query>),
+    Or(Vec<Query>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SizeComparison {
+    Less,
+    LessOrEqual,
+    Equal,
+    GreaterOrEqual,
+    Greater,
+}
+
+impl SizeComparison {
+    fn compare(self, size: u64, bytes: u64) -> bool {
+        match self {
+            SizeComparison::Less => size < bytes,
+            SizeComparison::LessOrEqual => size <= bytes,
+            SizeComparison::Equal => size == bytes,
+            SizeComparison::GreaterOrEqual => size >= bytes,
+            SizeComparison::Greater => size > bytes,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseQueryError {
+    pub message: String,
+    /// Byte offset of the part of the query that could not be parsed.
+    pub position: usize,
+}
+
+impl ParseQueryError {
+    fn new(message: impl Into<String>, position: usize) -> Self {
+        ParseQueryError {
+            message: message.into(),
+            position,
+        }
+    }
+}
+
+impl fmt::Display for ParseQueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at position {}", self.message, self.position)
+    }
+}
+
+impl std::error::Error for ParseQueryError {}
+
+impl Query {
+    /// Parses the query language described on [`Query`]. An empty query matches every file.
+    pub fn parse(query: &str) -> Result<Query, ParseQueryError> {
+        let mut parser = Parser {
+            tokens: tokenize(query)?,
+            next: 0,
+            end: query.len(),
+        };
+        if parser.tokens.is_empty() {
+            return Ok(Query::And(Vec::new()));
+        }
+        let parsed = parser.parse_or()?;
+        match parser.tokens.get(parser.next) {
+            Some(token) => Err(ParseQueryError::new("Unmatched \")\"", token.position)),
+            None => Ok(parsed),
+        }
+    }
+
+    pub fn archive(archive_type: &ArchiveType) -> Query {
+        Query::Archive(archive_type.to_string().to_lowercase())
+    }
+
+    pub fn language(language: &LanguageCode) -> Query {
+        Query::Language(language.to_string())
+    }
+
+    pub fn compression(compression_type: CompressionTypeT) -> Query {
+        Query::Compression(compression_type)
+    }
+
+    pub fn size(comparison: SizeComparison, bytes: u64) -> Query {
+        Query::Size(comparison, bytes)
+    }
+
+    pub fn glob(glob: &str) -> Query {
+        Query::Glob(glob_regex(glob))
+    }
+
+    pub fn regex(regex: &str) -> Result<Query, ParseQueryError> {
+        Regex::new(regex)
+            .map(Query::Regex)
+            .map_err(|err| ParseQueryError::new(format!("Invalid regex: {}", err), 0))
+    }
+
+    pub fn and(self, other: Query) -> Query {
+        match self {
+            Query::And(mut queries) => {
+                queries.push(other);
+                Query::And(queries)
+            }
+            query => Query::And(vec![query, other]),
+        }
+    }
+
+    pub fn or(self, other: Query) -> Query {
+        match self {
+            Query::Or(mut queries) => {
+                queries.push(other);
+                Query::Or(queries)
+            }
+            query => Query::Or(vec![query, other]),
+        }
+    }
+
+    pub fn matches(&self, file: &ArchiveFile) -> bool {
+        match self {
+            Query::Archive(name) => file.archive_type.to_string().eq_ignore_ascii_case(name),
+            Query::Language(code) => file.language.to_string().eq_ignore_ascii_case(code),
+            Query::Compression(compression_type) => file.compression_type() == *compression_type,
+            Query::Size(comparison, bytes) => comparison.compare(file.uncompressed_size(), *bytes),
+            Query::Glob(regex) => regex.is_match(&normalize_archive_path(file.path)),
+            Query::Regex(regex) => regex.is_match(file.path),
+            Query::Not(query) => !query.matches(file),
+            Query::And(queries) => queries.iter().all(|query| query.matches(file)),
+            Query::Or(queries) => queries.iter().any(|query| query.matches(file)),
+        }
+    }
+}
+
+impl std::ops::Not for Query {
+    type Output = Query;
+
+    fn not(self) -> Query {
+        Query::Not(Box::new(self))
+    }
+}
+
+impl std::str::FromStr for Query {
+    type Err = ParseQueryError;
+
+    fn from_str(s: &str) -> Result<Self, ParseQueryError> {
+        Query::parse(s)
+    }
+}
+
+// Globs are written against the same normalized form the paths are compared in.
+fn glob_regex(glob: &str) -> Regex {
+    let glob = normalize_archive_path(glob);
+    let mut regex = String::from(if glob.contains('\\') {
+        "^"
+    } else {
+        r"(?:^|\\)"
+    });
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                regex.push_str(".*");
+            }
+            '*' => regex.push_str(r"[^\\]*"),
+            '?' => regex.push_str(r"[^\\]"),
+            c => regex.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+        }
+    }
+    regex.push('$');
+    Regex::new(&regex).expect("every character of the glob is escaped")
+}
+
+#[derive(Debug)]
+enum TokenKind {
+    Open,
+    Close,
+    /// `quoted` is set when the word starts with a quote, it is then never a keyword or a key.
+    Word {
+        text: String,
+        quoted: bool,
+    },
+}
+
+#[derive(Debug)]
+struct Token {
+    kind: TokenKind,
+    position: usize,
+}
+
+fn tokenize(query: &str) -> Result<Vec<Token>, ParseQueryError> {
+    let mut tokens = Vec::new();
+    let mut chars = query.char_indices().peekable();
+    while let Some(&(position, c)) = chars.peek() {
+        let kind = match c {
+            c if c.is_whitespace() => {
+                chars.next();
+                continue;
+            }
+            '(' | ')' => {
+                chars.next();
+                if c == '(' {
+                    TokenKind::Open
+                } else {
+                    TokenKind::Close
+                }
+            }
+            _ => {
+                let mut text = String::new();
+                let mut open_quote = None;
+                while let Some(&(index, c)) = chars.peek() {
+                    if open_quote.is_none() && (c.is_whitespace() || c == ')') {
+                        break;
+                    }
+                    chars.next();
+                    match c {
+                        '"' if open_quote.is_none() => open_quote = Some(index),
+                        '"' => open_quote = None,
+                        c => text.push(c),
+                    }
+                }
+                if let Some(index) = open_quote {
+                    return Err(ParseQueryError::new("Unclosed quote", index));
+                }
+                TokenKind::Word {
+                    text,
+                    quoted: c == '"',
+                }
+            }
+        };
+        tokens.push(Token { kind, position });
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    next: usize,
+    end: usize,
+}
+
+impl Parser {
+    fn peek_keyword(&self, keyword: &str) -> bool {
+        matches!(
+            self.tokens.get(self.next),
+            Some(Token { kind: TokenKind::Word { text, quoted: false }, .. })
+                if text.eq_ignore_ascii_case(keyword)
+        )
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let found = self.peek_keyword(keyword);
+        if found {
+            self.next += 1;
+        }
+        found
+    }
+
+    fn parse_or(&mut self) -> Result<Query, ParseQueryError> {
+        let mut queries = vec![self.parse_and()?];
+        while self.eat_keyword("or") {
+            queries.push(self.parse_and()?);
+        }
+        Ok(match queries.len() {
+            1 => queries.remove(0),
+            _ => Query::Or(queries),
+        })
+    }
+
+    fn parse_and(&mut self) -> Result<Query, ParseQueryError> {
+        let mut queries = vec![self.parse_unary()?];
+        loop {
+            match self.tokens.get(self.next) {
+                None
+                | Some(Token {
+                    kind: TokenKind::Close,
+                    ..
+                }) => break,
+                _ if self.peek_keyword("or") => break,
+                _ => {}
+            }
+            self.eat_keyword("and");
+            queries.push(self.parse_unary()?);
+        }
+        Ok(match queries.len() {
+            1 => queries.remove(0),
+            _ => Query::And(queries),
+        })
+    }
+
+    fn parse_unary(&mut self) -> Result<Query, ParseQueryError> {
+        if self.eat_keyword("not") {
+            return Ok(!self.parse_unary()?);
+        }
+        let Some(token) = self.tokens.get(self.next) else {
+            return Err(ParseQueryError::new("Expected a term", self.end));
+        };
+        let position = token.position;
+        self.next += 1;
+        match &token.kind {
+            TokenKind::Open => {
+                let query = self.parse_or()?;
+                match self.tokens.get(self.next) {
+                    Some(Token {
+                        kind: TokenKind::Close,
+                        ..
+                    }) => {
+                        self.next += 1;
+                        Ok(query)
+                    }
+                    _ => Err(ParseQueryError::new("Unclosed \"(\"", position)),
+                }
+            }
+            TokenKind::Close => Err(ParseQueryError::new("Expected a term", position)),
+            TokenKind::Word { text, quoted: true } => Ok(Query::glob(text)),
+            TokenKind::Word {
+                text,
+                quoted: false,
+            } => {
+                if ["and", "or"].iter().any(|k| text.eq_ignore_ascii_case(k)) {
+                    return Err(ParseQueryError::new(
+                        format!("Expected a term before \"{}\"", text),
+                        position,
+                    ));
+                }
+                parse_term(text, position)
+            }
+        }
+    }
+}
+
+fn parse_term(text: &str, position: usize) -> Result<Query, ParseQueryError> {
+    if let Some(rest) = text
+        .get(..4)
+        .filter(|key| key.eq_ignore_ascii_case("size"))
+        .map(|_| &text[4..])
+        .filter(|rest| rest.starts_with(['<', '=', '>']))
+    {
+        return parse_size(rest, position + 4);
+    }
+    let Some((key, value)) = text.split_once(':') else {
+        return Ok(Query::glob(text));
+    };
+    let value_position = position + key.len() + 1;
+    match key.to_lowercase().as_str() {
+        "archive" => Ok(Query::Archive(value.to_lowercase())),
+        "lang" | "language" => Ok(Query::Language(value.to_lowercase())),
+        "compression" => value
+            .parse()
+            .map(Query::Compression)
+            .map_err(|err| ParseQueryError::new(err.to_string(), value_position)),
+        "path" => Ok(Query::glob(value)),
+        "re" | "regex" => Query::regex(value).map_err(|err| ParseQueryError {
+            position: value_position,
+            ..err
+        }),
+        _ => Err(ParseQueryError::new(
+            format!(
+                "Unknown key \"{}\", expected archive, lang, compression, path, re or size",
+                key
+            ),
+            position,
+        )),
+    }
+}
+
+fn parse_size(text: &str, position: usize) -> Result<Query, ParseQueryError> {
+    let (comparison, number) = [
+        ("<=", SizeComparison::LessOrEqual),
+        (">=", SizeComparison::GreaterOrEqual),
+        ("<", SizeComparison::Less),
+        (">", SizeComparison::Greater),
+        ("=", SizeComparison::Equal),
+    ]
+    .into_iter()
+    .find_map(|(operator, comparison)| {
+        text.strip_prefix(operator)
+            .map(|number| (comparison, number))
+    })
+    .expect("checked by the caller");
+    let position = position + text.len() - number.len();
+    let digits = number
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(number.len());
+    let multiplier = match number[digits..].to_uppercase().as_str() {
+        "" | "B" => 1u64,
+        "KB" => 1 << 10,
+        "MB" => 1 << 20,
+        "GB" => 1 << 30,
+        unit => {
+            return Err(ParseQueryError::new(
+                format!("Unknown size unit \"{}\", expected B, KB, MB or GB", unit),
+                position + digits,
+            ))
+        }
+    };
+    let value: f64 = number[..digits]
+        .parse()
+        .map_err(|_| ParseQueryError::new("Expected a size", position))?;
+    Ok(Query::Size(
+        comparison,
+        (value * multiplier as f64).round() as u64,
+    ))
+}
+
+/// Decides which files are extracted, listed or verified: the path has to match
+/// `extract_regex_filter` and one of the `include` queries (every file when there are none), and
+/// none of the `exclude` queries.
+#[derive(Debug, Clone, Default)]
+pub struct FileFilter {
+    regex: Option<Regex>,
+    include: Vec<Query>,
+    exclude: Vec<Query>,
+}
+
+impl FileFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_config(config: &Config) -> Result<Self, ParseQueryError> {
+        let mut filter = FileFilter::new();
+        if !config.extract_regex_filter.is_empty() {
+            filter.regex = Some(
+                Regex::new(&config.extract_regex_filter)
+                    .map_err(|err| ParseQueryError::new(format!("Invalid regex: {}", err), 0))?,
+            );
+        }
+        for include in &config.include {
+            filter = filter.include(Query::parse(include)?);
+        }
+        for exclude in &config.exclude {
+            filter = filter.exclude(Query::parse(exclude)?);
+        }
+        Ok(filter)
+    }
+
+    pub fn include(mut self, query: Query) -> Self {
+        self.include.push(query);
+        self
+    }
+
+    pub fn exclude(mut self, query: Query) -> Self {
+        self.exclude.push(query);
+        self
+    }
+
+    pub fn matches(&self, file: &ArchiveFile) -> bool {
+        self.regex.as_ref().is_none_or(|re| re.is_match(file.path))
+            && (self.include.is_empty() || self.include.iter().any(|query| query.matches(file)))
+            && !self.exclude.iter().any(|query| query.matches(file))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::oviiirs_archive::{FI, FIFLFSZZZ};
+    use crate::vfs::ArchiveFileSource;
+
+    #[test]
+    fn test_query_parse_and_match() {
+        let archive = FIFLFSZZZ {
+            archive_type: ArchiveType::Menu,
+            language: LanguageCode::Fr,
+            ..Default::default()
+        };
+        let fi = [
+            FI {
+                uncompressed_size: 3 << 20,
+                offset: 0,
+                compression_type: CompressionTypeT::Lzss,
+            },
+            FI {
+                uncompressed_size: 100,
+                offset: 0,
+                compression_type: CompressionTypeT::None,
+            },
+        ];
+        let file = |path, index: usize| ArchiveFile {
+            path,
+            archive_chain: Vec::new(),
+            language: archive.language.clone(),
+            archive_type: archive.archive_type.clone(),
+            source: ArchiveFileSource::FIFLFS {
+                archive: &archive,
+                fi: &fi[index],
+                index,
+            },
+        };
+        let texture = file("c:\\ff8\\data\\fre\\menu\\face\\Face1.TEX", 0);
+        let text = file("c:\\ff8\\data\\fre\\menu\\mngrp.bin", 1);
+
+        for (query, matches_texture, matches_text) in [
+            ("", true, true),
+            ("lang:fr archive:menu path:*.tex", true, false),
+            ("LANG:FR and archive:Menu and *.tex", true, false),
+            ("lang:en or compression:none", false, true),
+            ("not (size>1MB or re:bin$)", false, false),
+            ("size<=100 or path:ff8/data/fre/*/face/*", true, true),
+            ("path:data\\**\\face1.tex", false, false),
+            ("path:ff8\\data\\**\\face1.tex", true, false),
+            ("\"mngrp.bin\" and not compression:lzss", false, true),
+            ("size>=2.5mb size<3MB", false, false),
+        ] {
+            let parsed = Query::parse(query).unwrap();
+            assert_eq!(parsed.matches(&texture), matches_texture, "{}", query);
+            assert_eq!(parsed.matches(&text), matches_text, "{}", query);
+        }
+
+        let built = Query::language(&LanguageCode::Fr)
+            .and(Query::archive(&ArchiveType::Menu))
+            .and(Query::glob("*.tex").or(Query::glob("*.tim")));
+        assert!(built.matches(&texture) && !built.matches(&text));
+        assert!((!Query::size(SizeComparison::Greater, 100)).matches(&text));
+
+        for (query, position) in [
+            ("lang:fr (archive:menu", 8),
+            ("archive:menu)", 12),
+            ("not", 3),
+            ("or lang:fr", 0),
+            ("compression:zip", 12),
+            ("size>1TB", 6),
+            ("colour:red", 0),
+            ("re:\"(\"", 3),
+            ("path:\"a b", 5),
+        ] {
+            assert_eq!(
+                Query::parse(query).unwrap_err().position,
+                position,
+                "{}",
+                query
+            );
+        }
+
+        let config = Config {
+            extract_regex_filter: "menu".to_string(),
+            include: vec!["*.tex".to_string(), "size<1KB".to_string()],
+            exclude: vec!["compression:none".to_string()],
+            ..Default::default()
+        };
+        let filter = FileFilter::from_config(&config).unwrap();
+        assert!(filter.matches(&texture) && !filter.matches(&text));
+        assert!(FileFilter::new().matches(&text));
+        let config = Config {
+            exclude: vec!["lang:".to_string(), "(".to_string()],
+            ..Default::default()
+        };
+        assert!(FileFilter::from_config(&config).is_err());
+    }
+}
@@ -0,0 +1,226 @@
+use crate::oviiirs_archive::{
+    find_archives, find_loose_archives, process_files_in_directory, read_data_from_file,
+    LanguageCode,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::path::Path;
+
+/// Which FF8 release an install directory holds.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GameRelease {
+    /// No archives were found.
+    #[default]
+    Unknown,
+    /// The original PC release, with its fi/fl/fs loose in `Data`.
+    Original2000,
+    /// The 2013 Steam release, with loose fi/fl/fs per language in `Data/lang-xx`.
+    Steam2013,
+    /// The Remaster, with everything packed into `main.zzz` and `other.zzz`.
+    Remastered,
+}
+
+impl fmt::Display for GameRelease {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GameRelease::Unknown => write!(f, "Unknown"),
+            GameRelease::Original2000 => write!(f, "2000"),
+            GameRelease::Steam2013 => write!(f, "Steam 2013"),
+            GameRelease::Remastered => write!(f, "Remastered"),
+        }
+    }
+}
+
+/// What [`detect_release`] found in an install directory.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReleaseInfo {
+    pub release: GameRelease,
+    /// Codes of the languages present, like `en`, sorted.
+    #[serde(default)]
+    pub languages: Vec<String>,
+    /// `.zzz` files in the install directory.
+    #[serde(default)]
+    pub zzz_count: usize,
+    /// `.zzz` files whose header could not be read. Their archives and languages are missing
+    /// from the counts.
+    #[serde(default)]
+    pub unreadable_zzz_count: usize,
+    /// fi/fl/fs archives, packed in the `.zzz` files or loose, not counting those nested in a
+    /// field archive.
+    #[serde(default)]
+    pub archive_count: usize,
+}
+
+impl fmt::Display for ReleaseInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.release)?;
+        if !self.languages.is_empty() {
+            write!(f, " [{}]", self.languages.join(", "))?;
+        }
+        write!(
+            f,
+            ", {} zzz, {} fi/fl/fs archives",
+            self.zzz_count, self.archive_count
+        )?;
+        if self.unreadable_zzz_count > 0 {
+            write!(f, ", {} unreadable zzz", self.unreadable_zzz_count)?;
+        }
+        Ok(())
+    }
+}
+
+/// Classifies the install in `directory` from the archives it holds. Only the `.zzz` headers
+/// are read, and for the 2000 release the start of one fl to tell its language.
+pub fn detect_release(directory: &str) -> io::Result<ReleaseInfo> {
+    let directory = directory.to_string();
+    let mut info = ReleaseInfo::default();
+    let mut languages = BTreeSet::new();
+
+    let zzz_paths = process_files_in_directory(&directory)?;
+    info.zzz_count = zzz_paths.len();
+    for path in &zzz_paths {
+        // Only the Remaster has .zzz files, so one that is damaged still tells the release.
+        info.release = GameRelease::Remastered;
+        let Ok(zzz) = read_data_from_file(path) else {
+            info.unreadable_zzz_count += 1;
+            continue;
+        };
+        let archives = find_archives(zzz.entries, path);
+        info.archive_count += archives.len();
+        languages.extend(archives.iter().map(|archive| archive.language.to_string()));
+    }
+
+    if info.release == GameRelease::Unknown {
+        let archives = find_loose_archives(&directory)?;
+        info.archive_count = archives.len();
+        languages.extend(archives.iter().map(|archive| archive.language.to_string()));
+        if languages
+            .iter()
+            .any(|language| *language != LanguageCode::None.to_string())
+        {
+            info.release = GameRelease::Steam2013;
+        } else if let Some(archive) = archives.first() {
+            info.release = GameRelease::Original2000;
+            if let Some(language) = original_language(Path::new(archive.fl_file_path()))? {
+                languages.insert(language.to_string());
+            }
+        }
+    }
+
+    languages.remove(&LanguageCode::None.to_string());
+    info.languages = languages.into_iter().collect();
+    Ok(info)
+}
+
+// The 2000 release has one language, named in the paths of its fl like c:\ff8\data\eng\.
+fn original_language(fl_path: &Path) -> io::Result<Option<LanguageCode>> {
+    let mut start = Vec::new();
+    File::open(fl_path)?.take(512).read_to_end(&mut start)?;
+    let first_path = String::from_utf8_lossy(&start).to_lowercase();
+    let Some(language) = first_path
+        .split(['\\', '/'])
+        .skip_while(|component| *component != "data")
+        .nth(1)
+    else {
+        return Ok(None);
+    };
+    Ok(match language {
+        "eng" => Some(LanguageCode::En),
+        "fre" => Some(LanguageCode::Fr),
+        "ger" => Some(LanguageCode::De),
+        "ita" => Some(LanguageCode::It),
+        "spa" => Some(LanguageCode::Es),
+        "jpn" | "jp" => Some(LanguageCode::Jp),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+    use crate::zzz_writer::ZZZWriter;
+    use std::fs;
+
+    #[test]
+    fn test_detect_release() {
+        let directory = TempDir::new("release");
+        let detect = |name: &str| detect_release(directory.join(name).to_str().unwrap()).unwrap();
+
+        let original = directory.join("2000").join("Data");
+        fs::create_dir_all(&original).unwrap();
+        for stem in ["menu", "magic"] {
+            for extension in ["fi", "fs"] {
+                fs::write(original.join(format!("{}.{}", stem, extension)), b"").unwrap();
+            }
+            fs::write(
+                original.join(format!("{}.fl", stem)),
+                format!("C:\\ff8\\Data\\Fre\\{}\\a.bin\r\n", stem),
+            )
+            .unwrap();
+        }
+        assert_eq!(
+            detect("2000"),
+            ReleaseInfo {
+                release: GameRelease::Original2000,
+                languages: vec!["fr".to_string()],
+                zzz_count: 0,
+                unreadable_zzz_count: 0,
+                archive_count: 2,
+            }
+        );
+
+        for language in ["lang-en", "lang-it"] {
+            let data = directory.join("2013").join("Data").join(language);
+            fs::create_dir_all(&data).unwrap();
+            for extension in ["fi", "fl", "fs"] {
+                fs::write(data.join(format!("field.{}", extension)), b"").unwrap();
+            }
+        }
+        let info = detect("2013");
+        assert_eq!(info.release, GameRelease::Steam2013);
+        assert_eq!(info.languages, ["en", "it"]);
+        assert_eq!(info.archive_count, 2);
+
+        let remastered = directory.join("remastered");
+        fs::create_dir_all(&remastered).unwrap();
+        let mut zzz_writer = ZZZWriter::new();
+        for path in [
+            "data\\lang-de\\menu.fi",
+            "data\\lang-de\\menu.fl",
+            "data\\lang-de\\menu.fs",
+            "data\\music\\dmusic\\ogg\\00.ogg",
+        ] {
+            zzz_writer.add_bytes(path, b"1234".to_vec()).unwrap();
+        }
+        zzz_writer
+            .write_to_file(remastered.join("main.zzz").to_str().unwrap())
+            .unwrap();
+        ZZZWriter::new()
+            .write_to_file(remastered.join("other.zzz").to_str().unwrap())
+            .unwrap();
+        let info = detect("remastered");
+        assert_eq!(
+            info.to_string(),
+            "Remastered [de], 2 zzz, 1 fi/fl/fs archives"
+        );
+
+        // A truncated .zzz is counted but does not hide the other one.
+        fs::write(remastered.join("other.zzz"), [0xFF, 0xFF]).unwrap();
+        let info = detect("remastered");
+        assert_eq!(info.release, GameRelease::Remastered);
+        assert_eq!(info.languages, ["de"]);
+        assert_eq!(info.unreadable_zzz_count, 1);
+        assert_eq!(
+            info.to_string(),
+            "Remastered [de], 2 zzz, 1 fi/fl/fs archives, 1 unreadable zzz"
+        );
+
+        fs::create_dir_all(directory.join("empty")).unwrap();
+        assert_eq!(detect("empty").release, GameRelease::Unknown);
+    }
+}
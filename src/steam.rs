@@ -0,0 +1,398 @@
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Steam app id of the 2013 Steam release.
+pub const FF8_STEAM_APP_ID: u32 = 39150;
+/// Steam app id of the Remaster.
+pub const FF8_REMASTERED_APP_ID: u32 = 1026680;
+
+// Where the 2000 release installs itself inside a Wine prefix.
+const FF8_2000_PREFIX_DIRECTORIES: [&str; 2] = [
+    "drive_c/Program Files (x86)/Square Soft, Inc/Final Fantasy VIII",
+    "drive_c/Program Files/Square Soft, Inc/Final Fantasy VIII",
+];
+
+/// A node of Valve's KeyValues text format, used by `libraryfolders.vdf` and the
+/// `appmanifest_*.acf` files.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VdfValue {
+    String(String),
+    /// Keys in file order. Keys may repeat.
+    Object(Vec<(String, VdfValue)>),
+}
+
+impl VdfValue {
+    /// The first value of `key`, compared case-insensitively like Steam does.
+    pub fn get(&self, key: &str) -> Option<&VdfValue> {
+        match self {
+            VdfValue::Object(entries) => entries
+                .iter()
+                .find(|(entry_key, _)| entry_key.eq_ignore_ascii_case(key))
+                .map(|(_, value)| value),
+            VdfValue::String(_) => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            VdfValue::String(value) => Some(value),
+            VdfValue::Object(_) => None,
+        }
+    }
+
+    pub fn entries(&self) -> &[(String, VdfValue)] {
+        match self {
+            VdfValue::Object(entries) => entries,
+            VdfValue::String(_) => &[],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseVdfError {
+    pub message: String,
+    /// Line of the error, from 1.
+    pub line: usize,
+}
+
+impl fmt::Display for ParseVdfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ParseVdfError {}
+
+/// Parses a KeyValues text file into an object holding its top level keys.
+pub fn parse_vdf(text: &str) -> Result<VdfValue, ParseVdfError> {
+    let mut parser = VdfParser {
+        chars: text.chars().peekable(),
+        line: 1,
+    };
+    let entries = parser.parse_entries(false)?;
+    Ok(VdfValue::Object(entries))
+}
+
+struct VdfParser<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+    line: usize,
+}
+
+enum VdfToken {
+    String(String),
+    Open,
+    Close,
+}
+
+impl VdfParser<'_> {
+    fn error(&self, message: &str) -> ParseVdfError {
+        ParseVdfError {
+            message: message.to_string(),
+            line: self.line,
+        }
+    }
+
+    fn parse_entries(&mut self, nested: bool) -> Result<Vec<(String, VdfValue)>, ParseVdfError> {
+        let mut entries = Vec::new();
+        loop {
+            let key = match self.next_token()? {
+                Some(VdfToken::String(key)) => key,
+                Some(VdfToken::Close) if nested => return Ok(entries),
+                None if !nested => return Ok(entries),
+                Some(VdfToken::Close) => return Err(self.error("unexpected \"}\"")),
+                Some(VdfToken::Open) => return Err(self.error("expected a key before \"{\"")),
+                None => return Err(self.error("missing \"}\" at the end of the file")),
+            };
+            let value = match self.next_token()? {
+                Some(VdfToken::String(value)) => VdfValue::String(value),
+                Some(VdfToken::Open) => VdfValue::Object(self.parse_entries(true)?),
+                _ => return Err(self.error(&format!("missing the value of \"{}\"", key))),
+            };
+            entries.push((key, value));
+        }
+    }
+
+    fn next_token(&mut self) -> Result<Option<VdfToken>, ParseVdfError> {
+        loop {
+            match self.chars.next() {
+                None => return Ok(None),
+                Some('\n') => self.line += 1,
+                Some(c) if c.is_whitespace() => {}
+                Some('/') if self.chars.peek() == Some(&'/') => {
+                    while self.chars.next_if(|&c| c != '\n').is_some() {}
+                }
+                Some('{') => return Ok(Some(VdfToken::Open)),
+                Some('}') => return Ok(Some(VdfToken::Close)),
+                Some('"') => return self.quoted_string().map(|s| Some(VdfToken::String(s))),
+                Some(c) => {
+                    // Unquoted tokens end at whitespace or a brace.
+                    let mut token = c.to_string();
+                    while let Some(c) = self
+                        .chars
+                        .next_if(|&c| !c.is_whitespace() && c != '{' && c != '}' && c != '"')
+                    {
+                        token.push(c);
+                    }
+                    return Ok(Some(VdfToken::String(token)));
+                }
+            }
+        }
+    }
+
+    fn quoted_string(&mut self) -> Result<String, ParseVdfError> {
+        let mut value = String::new();
+        loop {
+            match self.chars.next() {
+                None => return Err(self.error("unclosed quote")),
+                Some('"') => return Ok(value),
+                Some('\\') => match self.chars.next() {
+                    Some('n') => value.push('\n'),
+                    Some('t') => value.push('\t'),
+                    Some(c) => value.push(c),
+                    None => return Err(self.error("unclosed quote")),
+                },
+                Some(c) => {
+                    if c == '\n' {
+                        self.line += 1;
+                    }
+                    value.push(c);
+                }
+            }
+        }
+    }
+}
+
+/// The Steam directories of a Linux user: the native ones, the symlinks in `~/.steam` and the
+/// Flatpak ones. Only the ones that exist are returned, each once.
+pub fn steam_roots(home: &Path) -> Vec<PathBuf> {
+    let candidates = [
+        ".local/share/Steam",
+        ".var/app/com.valvesoftware.Steam/.local/share/Steam",
+        ".var/app/com.valvesoftware.Steam/data/Steam",
+        ".steam/steam",
+        ".steam/root",
+    ];
+    let mut seen = BTreeSet::new();
+    candidates
+        .iter()
+        .map(|candidate| home.join(candidate))
+        .filter(|root| root.join("steamapps").is_dir())
+        .filter(|root| seen.insert(fs::canonicalize(root).unwrap_or_else(|_| root.clone())))
+        .collect()
+}
+
+/// The library folders listed in `steamapps/libraryfolders.vdf` of `root`, and `root` itself.
+pub fn steam_libraries(root: &Path) -> Vec<PathBuf> {
+    let mut libraries = vec![root.to_path_buf()];
+    let Ok(text) = fs::read_to_string(root.join("steamapps").join("libraryfolders.vdf")) else {
+        return libraries;
+    };
+    let Ok(vdf) = parse_vdf(&text) else {
+        return libraries;
+    };
+    let folders = vdf.get("libraryfolders");
+    for (key, value) in folders.map_or(&[][..], |folders| folders.entries()) {
+        // Older files map the index straight to the path, newer ones to an object holding it.
+        let path = match value {
+            VdfValue::String(path) if key.parse::<u32>().is_ok() => Some(path.as_str()),
+            VdfValue::Object(_) => value.get("path").and_then(VdfValue::as_str),
+            VdfValue::String(_) => None,
+        };
+        if let Some(path) = path {
+            let path = PathBuf::from(path);
+            if !libraries.contains(&path) {
+                libraries.push(path);
+            }
+        }
+    }
+    libraries
+}
+
+/// An FF8 install found through Steam.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SteamInstall {
+    /// The app the install belongs to. For the 2000 release that is the app whose Proton
+    /// prefix it was found in.
+    pub app_id: u32,
+    pub directory: PathBuf,
+}
+
+/// Finds the 2013 and Remaster installs in the library folders of every Steam root under
+/// `home`, and 2000 installs inside their Proton prefixes.
+pub fn find_steam_installs(home: &Path) -> Vec<SteamInstall> {
+    let mut installs: Vec<SteamInstall> = Vec::new();
+    for root in steam_roots(home) {
+        for library in steam_libraries(&root) {
+            for install in library_installs(&library) {
+                let canonical = |path: &Path| fs::canonicalize(path).ok();
+                if !installs
+                    .iter()
+                    .any(|found| canonical(&found.directory) == canonical(&install.directory))
+                {
+                    installs.push(install);
+                }
+            }
+        }
+    }
+    installs
+}
+
+fn library_installs(library: &Path) -> Vec<SteamInstall> {
+    let steamapps = library.join("steamapps");
+    let mut installs = Vec::new();
+    for app_id in [FF8_STEAM_APP_ID, FF8_REMASTERED_APP_ID] {
+        let manifest_path = steamapps.join(format!("appmanifest_{}.acf", app_id));
+        let Ok(text) = fs::read_to_string(manifest_path) else {
+            continue;
+        };
+        let install_directory = parse_vdf(&text).ok().and_then(|manifest| {
+            manifest
+                .get("AppState")?
+                .get("installdir")?
+                .as_str()
+                .map(str::to_string)
+        });
+        if let Some(install_directory) = install_directory {
+            let directory = steamapps.join("common").join(install_directory);
+            if directory.is_dir() {
+                installs.push(SteamInstall { app_id, directory });
+            }
+        }
+    }
+
+    let Ok(prefixes) = fs::read_dir(steamapps.join("compatdata")) else {
+        return installs;
+    };
+    let mut prefixes: Vec<PathBuf> = prefixes.flatten().map(|entry| entry.path()).collect();
+    prefixes.sort();
+    for prefix in prefixes {
+        let Some(app_id) = prefix
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.parse::<u32>().ok())
+        else {
+            continue;
+        };
+        for candidate in FF8_2000_PREFIX_DIRECTORIES {
+            let directory = prefix.join("pfx").join(candidate);
+            if directory.is_dir() {
+                installs.push(SteamInstall { app_id, directory });
+            }
+        }
+    }
+    installs
+}
+
+/// The FF8 installs found through Steam in the home directory of the current user.
+pub fn discover_install_directories() -> Vec<String> {
+    let Some(home) = dirs::home_dir() else {
+        return Vec::new();
+    };
+    find_steam_installs(&home)
+        .into_iter()
+        .filter_map(|install| install.directory.to_str().map(str::to_string))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn test_parse_vdf() {
+        let vdf = parse_vdf(
+            "// comment\n\"AppState\"\n{\n\t\"appid\"\t\t\"39150\"\n\t\"name\" \"FINAL FANTASY \\\"VIII\\\"\"\n\tUserConfig { language english }\n}\n",
+        )
+        .unwrap();
+        let app_state = vdf.get("appstate").unwrap();
+        assert_eq!(
+            app_state.get("appid").and_then(VdfValue::as_str),
+            Some("39150")
+        );
+        assert_eq!(
+            app_state.get("name").and_then(VdfValue::as_str),
+            Some("FINAL FANTASY \"VIII\"")
+        );
+        assert_eq!(
+            app_state
+                .get("UserConfig")
+                .and_then(|config| config.get("language"))
+                .and_then(VdfValue::as_str),
+            Some("english")
+        );
+        assert_eq!(parse_vdf("\"a\"\n{\n\"b\" \"c\"\n").unwrap_err().line, 4);
+        assert!(parse_vdf("\"a\" \"b\" }").is_err());
+    }
+
+    #[test]
+    fn test_find_steam_installs() {
+        let home = TempDir::new("steam");
+        let native = home.join(".local/share/Steam");
+        let flatpak = home.join(".var/app/com.valvesoftware.Steam/.local/share/Steam");
+        let library = home.join("games/SteamLibrary");
+        for directory in [
+            native.join("steamapps/common/FINAL FANTASY VIII"),
+            library.join("steamapps/common/FINAL FANTASY VIII Remastered"),
+            flatpak
+                .join("steamapps/compatdata/2147483649/pfx")
+                .join(FF8_2000_PREFIX_DIRECTORIES[0]),
+            flatpak.join("steamapps/compatdata/39150/pfx/drive_c/windows"),
+        ] {
+            fs::create_dir_all(directory).unwrap();
+        }
+        // The ~/.steam/steam symlink points at the native root, which is only searched once.
+        fs::create_dir_all(home.join(".steam")).unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&native, home.join(".steam/steam")).unwrap();
+
+        fs::write(
+            native.join("steamapps/libraryfolders.vdf"),
+            format!(
+                "\"libraryfolders\"\n{{\n\t\"0\"\n\t{{\n\t\t\"path\"\t\t\"{}\"\n\t}}\n\t\"1\"\n\t{{\n\t\t\"path\"\t\t\"{}\"\n\t\t\"apps\" {{ \"1026680\" \"0\" }}\n\t}}\n}}\n",
+                native.display(),
+                library.display()
+            ),
+        )
+        .unwrap();
+        fs::write(
+            native.join("steamapps/appmanifest_39150.acf"),
+            "\"AppState\"\n{\n\t\"appid\"\t\t\"39150\"\n\t\"installdir\"\t\t\"FINAL FANTASY VIII\"\n}\n",
+        )
+        .unwrap();
+        fs::write(
+            library.join("steamapps/appmanifest_1026680.acf"),
+            "\"AppState\"\n{\n\t\"appid\"\t\t\"1026680\"\n\t\"installdir\"\t\t\"FINAL FANTASY VIII Remastered\"\n}\n",
+        )
+        .unwrap();
+        // Another game's manifest is ignored.
+        fs::write(
+            library.join("steamapps/appmanifest_39140.acf"),
+            "\"AppState\" { \"installdir\" \"FINAL FANTASY VII\" }",
+        )
+        .unwrap();
+
+        assert_eq!(steam_roots(&home).len(), 2);
+        assert_eq!(
+            find_steam_installs(&home),
+            vec![
+                SteamInstall {
+                    app_id: FF8_STEAM_APP_ID,
+                    directory: native.join("steamapps/common/FINAL FANTASY VIII"),
+                },
+                SteamInstall {
+                    app_id: FF8_REMASTERED_APP_ID,
+                    directory: library.join("steamapps/common/FINAL FANTASY VIII Remastered"),
+                },
+                SteamInstall {
+                    app_id: 2147483649,
+                    directory: flatpak
+                        .join("steamapps/compatdata/2147483649/pfx")
+                        .join(FF8_2000_PREFIX_DIRECTORIES[0]),
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,66 @@
+use crate::oviiirs_archive::{
+    find_archives, read_data_from_file, ConvertFromZZZEntryAndFile, FIfile, FLfile, ZZZHeader,
+};
+use std::fs;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static NEXT_TEMP_DIR: AtomicUsize = AtomicUsize::new(0);
+
+/// A new directory under the system temp directory, removed again when it is dropped, also when
+/// the test panics. Every one gets its own name, so tests running at the same time never share
+/// a directory.
+#[derive(Debug)]
+pub(crate) struct TempDir(PathBuf);
+
+impl TempDir {
+    pub(crate) fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!(
+            "oviiirs_{}_{}_{}",
+            name,
+            std::process::id(),
+            NEXT_TEMP_DIR.fetch_add(1, Ordering::Relaxed)
+        ));
+        // Left over from a run that was killed before it could clean up.
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for TempDir {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Reads the `.zzz` at `zzz_path` with the fi and fl of each archive in it loaded, the way the
+/// binary loads an install.
+pub(crate) fn load_zzz(zzz_path: &str) -> ZZZHeader {
+    let zzz_path = zzz_path.to_string();
+    let mut zzz = read_data_from_file(&zzz_path).unwrap();
+    let mut archives = find_archives(zzz.entries.clone(), &zzz_path);
+    for archive in archives.iter_mut() {
+        archive.fi_file =
+            Some(FIfile::from_zzz_entry_and_file(&archive.fi, &archive.file_path).unwrap());
+        archive.fl_file =
+            Some(FLfile::from_zzz_entry_and_file(&archive.fl, &archive.file_path).unwrap());
+    }
+    zzz.fiflfs_files = Some(archives);
+    zzz
+}
@@ -0,0 +1,363 @@
+use crate::error::ArchiveResult;
+use crate::extract::FileCopier;
+use crate::oviiirs_archive::{
+    ArchiveType, CompressionTypeT, GenerateWindowsPath, LanguageCode, ZZZEntry, ZZZHeader,
+    ZZZfiles, FI, FIFLFSZZZ,
+};
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::io::Cursor;
+use std::io::Read;
+use std::sync::Mutex;
+
+/// Read-only view of a game install as one tree of files. Paths are the ZZZ `string_data` or
+/// FL strings, compared without regard to case, separator style or the `c:\` prefix. Errors
+/// reading an archive are `ArchiveError`s wrapped in the `io::Error`, `ArchiveError::from`
+/// recovers them.
+pub trait VirtualFs {
+    fn open(&self, path: &str) -> io::Result<impl Read>;
+    fn metadata(&self, path: &str) -> io::Result<VfsMetadata>;
+    fn read_dir(&self, path: &str) -> io::Result<Vec<VfsDirEntry>>;
+    fn exists(&self, path: &str) -> bool;
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VfsMetadata {
+    pub is_dir: bool,
+    /// Size of the file once decompressed, 0 for directories.
+    pub len: u64,
+    pub compression_type: CompressionTypeT,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VfsDirEntry {
+    /// Last component of the path, as it is spelled in the archive.
+    pub name: String,
+    pub path: String,
+    pub is_dir: bool,
+}
+
+/// Where the bytes of an `ArchiveFile` are stored.
+#[derive(Debug, Clone, Copy)]
+pub enum ArchiveFileSource<'a> {
+    /// An entry of a `.zzz`.
+    ZZZ {
+        zzz: &'a ZZZHeader,
+        entry: &'a ZZZEntry,
+    },
+    /// An entry of a FIFLFS archive, which is either inside a `.zzz`, loose on disk or nested
+    /// inside field.
+    FIFLFS {
+        archive: &'a FIFLFSZZZ,
+        fi: &'a FI,
+        index: usize,
+    },
+}
+
+/// A file that can be read out of the install.
+#[derive(Debug, Clone)]
+pub struct ArchiveFile<'a> {
+    pub path: &'a str,
+    /// The archives this file is nested in, outermost first. For a file in a field map this is
+    /// the zzz (or the install directory for loose files), the field fs and the map's fs.
+    pub archive_chain: Vec<&'a str>,
+    /// Language of the innermost archive that has one, `None` for `.zzz` entries.
+    pub language: LanguageCode,
+    /// Type of the outermost fi/fl/fs archive, so files of a field map are `Field`.
+    pub archive_type: ArchiveType,
+    pub source: ArchiveFileSource<'a>,
+}
+
+impl ArchiveFile<'_> {
+    pub fn uncompressed_size(&self) -> u64 {
+        match self.source {
+            ArchiveFileSource::ZZZ { entry, .. } => entry.file_size as u64,
+            ArchiveFileSource::FIFLFS { fi, .. } => fi.uncompressed_size as u64,
+        }
+    }
+
+    pub fn compression_type(&self) -> CompressionTypeT {
+        match self.source {
+            ArchiveFileSource::ZZZ { .. } => CompressionTypeT::None,
+            ArchiveFileSource::FIFLFS { fi, .. } => fi.compression_type,
+        }
+    }
+
+    /// Reads and decompresses the whole file. Reading several files of the same fs is faster
+    /// with one [`FileCopier`], which decompresses a compressed fs only once.
+    pub fn read(&self) -> ArchiveResult<Vec<u8>> {
+        FileCopier::new().read_file(self)
+    }
+}
+
+// The path in Windows form without the c:\ prefix, keeping its spelling.
+fn strip_archive_path(path: &str) -> String {
+    let path = path.trim().generate_windows_path();
+    let path = path.trim_matches('\\');
+    match path.get(..3) {
+        Some(prefix) if prefix.eq_ignore_ascii_case("c:\\") => path[3..].to_string(),
+        _ => path.to_string(),
+    }
+}
+
+// FL and ZZZ paths are compared the way the game looks them up: case-insensitive, with or
+// without the c:\ prefix.
+pub fn normalize_archive_path(path: &str) -> String {
+    strip_archive_path(path).to_lowercase()
+}
+
+/// Every file in the install: the `.zzz` entries, the entries of each FIFLFS archive and those
+/// nested in field. Archives whose fi and fl are not loaded yet are skipped, as are the empty
+/// placeholder entries of the fi files.
+pub fn archive_files(zzz_files: &ZZZfiles) -> Vec<ArchiveFile<'_>> {
+    let mut files = Vec::new();
+    for zzz in zzz_files.into_iter().flatten() {
+        for entry in &zzz.entries {
+            files.push(ArchiveFile {
+                path: &entry.string_data,
+                archive_chain: vec![&zzz.file_path],
+                language: LanguageCode::None,
+                archive_type: ArchiveType::None,
+                source: ArchiveFileSource::ZZZ { zzz, entry },
+            });
+        }
+        for archive in zzz.fiflfs_files.iter().flatten() {
+            push_fiflfs_files(
+                &mut files,
+                archive,
+                vec![&zzz.file_path],
+                &archive.language,
+                &archive.archive_type,
+            );
+        }
+    }
+    files
+}
+
+fn push_fiflfs_files<'a>(
+    files: &mut Vec<ArchiveFile<'a>>,
+    archive: &'a FIFLFSZZZ,
+    mut archive_chain: Vec<&'a str>,
+    parent_language: &LanguageCode,
+    archive_type: &ArchiveType,
+) {
+    archive_chain.push(&archive.fs.string_data);
+    // The maps nested in field have no language of their own.
+    let language = match archive.language {
+        LanguageCode::None => parent_language,
+        ref language => language,
+    };
+    if let (Some(fi_file), Some(fl_file)) = (archive.fi_file.as_ref(), archive.fl_file.as_ref()) {
+        for (index, (fi, fl)) in fi_file.entries.iter().zip(&fl_file.entries).enumerate() {
+            if fi.uncompressed_size == 0 {
+                continue;
+            }
+            files.push(ArchiveFile {
+                path: fl,
+                archive_chain: archive_chain.clone(),
+                language: language.clone(),
+                archive_type: archive_type.clone(),
+                source: ArchiveFileSource::FIFLFS { archive, fi, index },
+            });
+        }
+    }
+    for child in archive.field_archives.iter().flatten() {
+        push_fiflfs_files(files, child, archive_chain.clone(), language, archive_type);
+    }
+}
+
+impl ZZZfiles {
+    /// Looks a file up by path. When the same path is stored twice the first one found wins,
+    /// main.zzz before other.zzz. This walks every file, [`ArchiveFs`] is faster for repeated
+    /// lookups.
+    pub fn find_file(&self, path: &str) -> Option<ArchiveFile<'_>> {
+        let target = normalize_archive_path(path);
+        archive_files(self)
+            .into_iter()
+            .find(|file| normalize_archive_path(file.path) == target)
+    }
+}
+
+/// A [`VirtualFs`] over the archives of an install. Every path is indexed once when it is
+/// created, so lookups do not walk the archives.
+pub struct ArchiveFs<'a> {
+    /// Keyed by the normalized path. When the same path is stored twice the first one found
+    /// wins, main.zzz before other.zzz.
+    files: HashMap<String, ArchiveFile<'a>>,
+    /// The children of each directory, keyed by the normalized directory path, empty for the
+    /// root, then by the lowercase name so children that only differ in case are merged.
+    directories: HashMap<String, BTreeMap<String, VfsDirEntry>>,
+    /// Keeps the last fs read open, so opening the files of a compressed fs one after another
+    /// decompresses it once.
+    copier: Mutex<FileCopier<'a>>,
+}
+
+impl<'a> ArchiveFs<'a> {
+    pub fn new(zzz_files: &'a ZZZfiles) -> Self {
+        let mut files = HashMap::new();
+        let mut directories = HashMap::<String, BTreeMap<String, VfsDirEntry>>::new();
+        directories.insert(String::new(), BTreeMap::new());
+        for file in archive_files(zzz_files) {
+            let spelled = strip_archive_path(file.path);
+            let normalized = spelled.to_lowercase();
+            let components = spelled.split('\\').collect::<Vec<_>>();
+            for depth in 0..components.len() {
+                let directory = components[..depth].join("\\").to_lowercase();
+                let name = components[depth];
+                directories
+                    .entry(directory)
+                    .or_default()
+                    .entry(name.to_lowercase())
+                    .or_insert_with(|| VfsDirEntry {
+                        name: name.to_string(),
+                        path: components[..=depth].join("\\"),
+                        is_dir: depth + 1 < components.len(),
+                    });
+            }
+            files.entry(normalized).or_insert(file);
+        }
+        ArchiveFs {
+            files,
+            directories,
+            copier: Mutex::new(FileCopier::new()),
+        }
+    }
+
+    pub fn find_file(&self, path: &str) -> Option<&ArchiveFile<'a>> {
+        self.files.get(&normalize_archive_path(path))
+    }
+
+    fn is_directory(&self, path: &str) -> bool {
+        self.directories.contains_key(&normalize_archive_path(path))
+    }
+}
+
+impl VirtualFs for ArchiveFs<'_> {
+    fn open(&self, path: &str) -> io::Result<impl Read> {
+        let file = self.find_file(path).ok_or_else(|| not_found(path))?;
+        Ok(Cursor::new(self.copier.lock().unwrap().read_file(file)?))
+    }
+
+    fn metadata(&self, path: &str) -> io::Result<VfsMetadata> {
+        if let Some(file) = self.find_file(path) {
+            return Ok(VfsMetadata {
+                is_dir: false,
+                len: file.uncompressed_size(),
+                compression_type: file.compression_type(),
+            });
+        }
+        if self.is_directory(path) {
+            return Ok(VfsMetadata {
+                is_dir: true,
+                len: 0,
+                compression_type: CompressionTypeT::None,
+            });
+        }
+        Err(not_found(path))
+    }
+
+    fn read_dir(&self, path: &str) -> io::Result<Vec<VfsDirEntry>> {
+        self.directories
+            .get(&normalize_archive_path(path))
+            .map(|children| children.values().cloned().collect())
+            .ok_or_else(|| not_found(path))
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.find_file(path).is_some() || self.is_directory(path)
+    }
+}
+
+fn not_found(path: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("{} is not in any archive", path),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fiflfs_writer::FIFLFSWriter;
+    use crate::test_util::{load_zzz, TempDir};
+    use crate::zzz_writer::ZZZWriter;
+
+    #[test]
+    fn test_virtual_fs_over_zzz() {
+        let directory = TempDir::new("vfs");
+        let zzz_path = directory.join("main.zzz").to_str().unwrap().to_string();
+
+        let mut menu_writer = FIFLFSWriter::new();
+        menu_writer
+            .add_bytes(
+                "c:\\ff8\\data\\eng\\menu\\Mngrp.bin",
+                b"menu groups".to_vec(),
+                CompressionTypeT::Lzss,
+            )
+            .unwrap();
+        menu_writer
+            .add_bytes(
+                "c:\\ff8\\data\\eng\\menu\\face\\face1.tex",
+                b"faces".to_vec(),
+                CompressionTypeT::Lz4,
+            )
+            .unwrap();
+        let mut zzz_writer = ZZZWriter::new();
+        zzz_writer
+            .add_bytes("data\\music\\dmusic\\ogg\\00.ogg", b"OggS".to_vec())
+            .unwrap();
+        menu_writer
+            .add_to_zzz(&mut zzz_writer, "data\\lang-en\\menu")
+            .unwrap();
+        zzz_writer.write_to_file(&zzz_path).unwrap();
+
+        let mut zzz_files = ZZZfiles::default();
+        assert!(zzz_files.push(load_zzz(&zzz_path)));
+        let archive_fs = ArchiveFs::new(&zzz_files);
+
+        let mut contents = String::new();
+        archive_fs
+            .open("FF8/DATA/ENG/MENU/mngrp.BIN")
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "menu groups");
+        let mut contents = Vec::new();
+        archive_fs
+            .open("data/music/dmusic/ogg/00.ogg")
+            .unwrap()
+            .read_to_end(&mut contents)
+            .unwrap();
+        assert_eq!(contents, b"OggS");
+
+        let metadata = archive_fs
+            .metadata("c:\\ff8\\data\\eng\\menu\\face\\face1.tex")
+            .unwrap();
+        assert_eq!(metadata.len, 5);
+        assert_eq!(metadata.compression_type, CompressionTypeT::Lz4);
+        assert!(archive_fs.metadata("ff8\\data\\eng\\menu").unwrap().is_dir);
+
+        let menu = archive_fs.read_dir("ff8/data/eng/menu").unwrap();
+        assert_eq!(
+            menu.iter()
+                .map(|entry| (entry.name.as_str(), entry.is_dir))
+                .collect::<Vec<_>>(),
+            vec![("face", true), ("Mngrp.bin", false)]
+        );
+        assert_eq!(menu[1].path, "ff8\\data\\eng\\menu\\Mngrp.bin");
+        let root = archive_fs.read_dir("").unwrap();
+        assert_eq!(
+            root.iter()
+                .map(|entry| entry.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["data", "ff8"]
+        );
+
+        assert!(archive_fs.exists("data\\lang-en\\menu.fs"));
+        assert!(archive_fs.exists("ff8\\data"));
+        assert!(!archive_fs.exists("ff8\\data\\eng\\field"));
+        assert!(archive_fs.open("missing").is_err());
+        assert!(archive_fs.read_dir("missing").is_err());
+        assert!(archive_fs.read_dir("ff8/data/eng/menu/mngrp.bin").is_err());
+    }
+}
@@ -0,0 +1,314 @@
+use crate::oviiirs_archive::{
+    read_data_from_file, CompressionTypeT, GenerateWindowsPath, WriteEntry, ZZZEntry, ZZZHeader,
+};
+use std::collections::HashSet;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+// Where the bytes of a pending entry come from. Files are only opened while writing so a whole
+// directory tree never has to sit in memory.
+#[derive(Debug, Clone)]
+enum ZZZSource {
+    Bytes(Vec<u8>),
+    File(PathBuf),
+    FileRange {
+        path: PathBuf,
+        offset: u64,
+        size: u64,
+    },
+}
+
+impl ZZZSource {
+    fn len(&self) -> io::Result<u64> {
+        match self {
+            ZZZSource::Bytes(bytes) => Ok(bytes.len() as u64),
+            ZZZSource::File(path) => Ok(fs::metadata(path)?.len()),
+            ZZZSource::FileRange { size, .. } => Ok(*size),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct ZZZPendingEntry {
+    string_data: String,
+    source: ZZZSource,
+}
+
+/// Builds a `.zzz` container: the u32 entry count, the entry table and then every payload in
+/// the same order as the table.
+#[derive(Debug, Default, Clone)]
+pub struct ZZZWriter {
+    entries: Vec<ZZZPendingEntry>,
+    known_paths: HashSet<String>,
+}
+
+impl ZZZWriter {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Collects every file below `directory`. The relative paths become the Windows style
+    /// `string_data` of the entries, e.g. `data\lang-en\field.fi`.
+    pub fn from_directory<P: AsRef<Path>>(directory: P) -> io::Result<Self> {
+        let directory = directory.as_ref();
+        let mut files = Vec::<PathBuf>::new();
+        collect_files(directory, &mut files)?;
+
+        let mut relative_files = files
+            .into_iter()
+            .map(|file| -> io::Result<(String, PathBuf)> {
+                let relative = file
+                    .strip_prefix(directory)
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?
+                    .to_str()
+                    .ok_or_else(|| {
+                        io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!("Path is not valid UTF-8: {}", file.display()),
+                        )
+                    })?
+                    .to_string();
+                Ok((relative, file))
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        // read_dir has no defined order, keep the output reproducible.
+        relative_files.sort_by_key(|(relative, _)| relative.to_lowercase());
+
+        let mut writer = ZZZWriter::new();
+        for (relative, file) in relative_files {
+            writer.add_file(&relative, file)?;
+        }
+        Ok(writer)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Adds an in-memory blob stored as `path` inside the archive.
+    pub fn add_bytes(&mut self, path: &str, data: Vec<u8>) -> io::Result<()> {
+        self.push(path, ZZZSource::Bytes(data))
+    }
+
+    /// Adds a file from disk that is stored as `path` inside the archive.
+    pub fn add_file<P: Into<PathBuf>>(&mut self, path: &str, source: P) -> io::Result<()> {
+        self.push(path, ZZZSource::File(source.into()))
+    }
+
+    /// Adds `size` bytes starting at `offset` of an existing file, e.g. an entry copied
+    /// unchanged from another `.zzz`.
+    pub fn add_file_range<P: Into<PathBuf>>(
+        &mut self,
+        path: &str,
+        source: P,
+        offset: u64,
+        size: u64,
+    ) -> io::Result<()> {
+        self.push(
+            path,
+            ZZZSource::FileRange {
+                path: source.into(),
+                offset,
+                size,
+            },
+        )
+    }
+
+    fn push(&mut self, path: &str, source: ZZZSource) -> io::Result<()> {
+        let string_data = path
+            .generate_windows_path()
+            .trim_start_matches('\\')
+            .to_string();
+        if string_data.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Archive path can not be empty",
+            ));
+        }
+        // The game looks entries up without regard to case.
+        if !self.known_paths.insert(string_data.to_lowercase()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Duplicate archive path: {}", string_data),
+            ));
+        }
+        self.entries.push(ZZZPendingEntry {
+            string_data,
+            source,
+        });
+        Ok(())
+    }
+
+    /// Computes the entry table without writing anything. Offsets are absolute within the
+    /// resulting `.zzz` file.
+    pub fn build_entries(&self) -> io::Result<Vec<ZZZEntry>> {
+        let header_size = std::mem::size_of::<u32>() as u64
+            + self
+                .entries
+                .iter()
+                .map(|entry| entry_header_size(&entry.string_data))
+                .sum::<u64>();
+
+        let mut file_offset = header_size;
+        self.entries
+            .iter()
+            .map(|entry| {
+                let size = entry.source.len()?;
+                let file_size = u32::try_from(size).map_err(|_| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!(
+                            "{} is {} bytes which does not fit in a ZZZ entry",
+                            entry.string_data, size
+                        ),
+                    )
+                })?;
+                let result = ZZZEntry {
+                    string_length: entry.string_data.len() as u32,
+                    string_data: entry.string_data.clone(),
+                    file_offset,
+                    file_size,
+                    compression_type: CompressionTypeT::None,
+                };
+                file_offset += size;
+                Ok(result)
+            })
+            .collect()
+    }
+
+    /// Writes the complete archive and returns the entry table that was written.
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<Vec<ZZZEntry>> {
+        let entries = self.build_entries()?;
+        let count = u32::try_from(entries.len()).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Too many entries for a ZZZ file",
+            )
+        })?;
+
+        writer.write_all(&count.to_le_bytes())?;
+        for entry in &entries {
+            entry.write_entry(writer)?;
+        }
+
+        for (pending, entry) in self.entries.iter().zip(&entries) {
+            let written = match &pending.source {
+                ZZZSource::Bytes(bytes) => {
+                    writer.write_all(bytes)?;
+                    bytes.len() as u64
+                }
+                ZZZSource::File(path) => {
+                    io::copy(&mut File::open(path)?.take(entry.file_size as u64), writer)?
+                }
+                ZZZSource::FileRange { path, offset, size } => {
+                    let mut file = File::open(path)?;
+                    file.seek(SeekFrom::Start(*offset))?;
+                    io::copy(&mut file.take(*size), writer)?
+                }
+            };
+            if written != entry.file_size as u64 {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    format!(
+                        "{} changed size while writing, expected {} bytes got {}",
+                        entry.string_data, entry.file_size, written
+                    ),
+                ));
+            }
+        }
+        writer.flush()?;
+        Ok(entries)
+    }
+
+    /// Writes the archive to `file_path` and reads the header back the same way an existing
+    /// `.zzz` would be loaded.
+    pub fn write_to_file(&self, file_path: &str) -> io::Result<ZZZHeader> {
+        let mut writer = BufWriter::new(File::create(file_path)?);
+        self.write(&mut writer)?;
+        drop(writer);
+        Ok(read_data_from_file(&file_path.to_string())?)
+    }
+}
+
+fn entry_header_size(string_data: &str) -> u64 {
+    (std::mem::size_of::<u32>()
+        + string_data.len()
+        + std::mem::size_of::<u64>()
+        + std::mem::size_of::<u32>()) as u64
+}
+
+fn collect_files(directory: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else if path.is_file() {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::oviiirs_archive::ReadEntries;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_zzz_writer_round_trip() {
+        let mut zzz_writer = ZZZWriter::new();
+        zzz_writer
+            .add_bytes("data/lang-en/field.fi", vec![1, 2, 3, 4])
+            .unwrap();
+        zzz_writer
+            .add_bytes(
+                "data\\lang-en\\field.fl",
+                b"c:\\ff8\\data\\eng\\a\r\n".to_vec(),
+            )
+            .unwrap();
+        zzz_writer.add_bytes("data\\empty", vec![]).unwrap();
+        assert!(zzz_writer
+            .add_bytes("DATA\\LANG-EN\\FIELD.FI", vec![])
+            .is_err());
+
+        let mut bytes = Vec::<u8>::new();
+        let written_entries = zzz_writer.write(&mut bytes).unwrap();
+
+        let mut reader = Cursor::new(&bytes);
+        let mut count_bytes = [0u8; 4];
+        reader.read_exact(&mut count_bytes).unwrap();
+        assert_eq!(u32::from_le_bytes(count_bytes), 3);
+        let read_entries = ZZZEntry::read_entries_with_limit(&mut reader, 3).unwrap();
+        assert_eq!(written_entries, read_entries);
+
+        assert_eq!(read_entries[0].string_data, "data\\lang-en\\field.fi");
+        assert_eq!(read_entries[1].string_data, "data\\lang-en\\field.fl");
+        assert_eq!(read_entries[0].file_offset, reader.position());
+
+        let payload = |entry: &ZZZEntry| {
+            let start = entry.file_offset as usize;
+            bytes[start..start + entry.file_size as usize].to_vec()
+        };
+        assert_eq!(payload(&read_entries[0]), vec![1, 2, 3, 4]);
+        assert_eq!(payload(&read_entries[1]), b"c:\\ff8\\data\\eng\\a\r\n");
+        assert!(payload(&read_entries[2]).is_empty());
+        assert_eq!(
+            bytes.len() as u64,
+            read_entries[2].file_offset + read_entries[2].file_size as u64
+        );
+    }
+}